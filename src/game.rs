@@ -1,5 +1,6 @@
 pub mod brush;
 pub mod camera_controller;
+pub mod cli;
 pub mod entities;
 pub mod events;
 pub mod game_state;
@@ -11,9 +12,9 @@ use crate::game::{
     brush::Brush,
     camera_controller::CameraController,
     entities::Entities,
-    game_state::{GameState, Mode},
+    game_state::{GameEvent, GameState, Mode},
     hud::Hud,
-    map::Map,
+    map::{Map, svg::SvgOptions, transform::MapTransform},
     theme::Theme,
 };
 
@@ -35,7 +36,16 @@ pub struct Game {
 impl Game {
     pub async fn load(&mut self) -> Result<()> {
         self.map.load_tile_variants().await?;
-        let _ = self.map.load_map().await;
+        if let Some(path) = std::env::args().nth(1) {
+            self.map.current_map_file = path;
+        }
+        if let Err(error) = self.map.load_map().await {
+            warn!("Loading {} failed: {error}", self.map.current_map_file);
+            // Quitting saves, which would write the empty map over the file that failed to load
+            if std::path::Path::new(&self.map.current_map_file).exists() {
+                self.map.current_map_file.clear();
+            }
+        }
         self.entities.load_textures().await?;
         self.entities.load_entities().await?;
         Ok(())
@@ -65,9 +75,10 @@ impl Game {
                 self.camera_controller.handle_events(dt)?;
                 self.camera_controller.update(&mut self.camera, dt)?;
                 self.hud.handle_events(dt)?;
+                self.map.handle_events(&self.camera, self.state.gm)?;
                 self.entities.update(dt)?;
                 self.entities
-                    .handle_events(&self.map.hex_layout, &self.camera, dt)?;
+                    .handle_events(&self.map.grid, &self.camera, dt)?;
             }
             Mode::MapEditor => {
                 self.camera_controller.handle_events(dt)?;
@@ -77,32 +88,85 @@ impl Game {
                     .await?;
                 self.brush.update(&self.map, &self.camera, dt)?;
                 self.hud.handle_events(dt)?;
+                if self.state.game_events.pop(&GameEvent::ExportSvg) {
+                    let path =
+                        std::path::Path::new(&self.map.current_map_file).with_extension("svg");
+                    let exported = self.map.export_svg(
+                        &path.to_string_lossy(),
+                        &self.theme,
+                        &SvgOptions {
+                            gm: self.state.gm,
+                            ..Default::default()
+                        },
+                    );
+                    if let Err(error) = exported {
+                        warn!("SVG export failed: {error}");
+                    }
+                }
+                self.transform_map();
             }
             Mode::Exiting => (),
         }
         Ok(())
     }
 
+    /// Turns, mirrors or moves the map and the entities on it
+    fn transform_map(&mut self) {
+        let pivot = self
+            .map
+            .grid
+            .world_to_cell(self.camera.screen_to_world(mouse_position().into()));
+        let events = &mut self.state.game_events;
+        let mut transforms = Vec::new();
+        for steps in [-1, 1] {
+            if events.pop(&GameEvent::RotateMap(steps)) {
+                transforms.push(MapTransform::Rotate {
+                    pivot,
+                    steps: steps as i32,
+                });
+            }
+        }
+        if events.pop(&GameEvent::MirrorMap) {
+            transforms.push(MapTransform::Mirror { pivot, axis: 0 });
+        }
+        if events.pop(&GameEvent::MapToOrigin) {
+            transforms.push(MapTransform::Translate(-pivot));
+        }
+        if events.pop(&GameEvent::NormalizeMap) {
+            transforms.push(self.map.normalizing());
+        }
+        for transform in transforms {
+            if let Err(error) = self.map.transform(transform) {
+                warn!("Map left as it was: {error}");
+                continue;
+            }
+            self.entities.transform(&self.map.grid, transform);
+            self.brush.clear_history();
+        }
+    }
+
     pub async fn draw(&mut self) -> Result<()> {
         match self.state.mode {
             Mode::Normal => {
                 set_camera(&self.camera);
-                self.map.draw(&self.theme);
+                self.map.draw(&self.theme, &self.camera, self.state.gm);
                 self.entities
-                    .draw(&self.theme, &self.map.hex_layout, &self.camera);
+                    .draw(&self.theme, &self.map.grid, &self.camera);
                 set_default_camera();
                 self.hud
                     .draw(&self.theme, &self.camera, &self.camera_controller);
             }
             Mode::MapEditor => {
                 set_camera(&self.camera);
-                self.map.draw(&self.theme);
+                self.map.draw(&self.theme, &self.camera, self.state.gm);
                 self.brush.draw(&self.map, &self.theme);
                 self.entities
-                    .draw(&self.theme, &self.map.hex_layout, &self.camera);
+                    .draw(&self.theme, &self.map.grid, &self.camera);
                 set_default_camera();
                 self.hud
                     .draw(&self.theme, &self.camera, &self.camera_controller);
+                self.hud
+                    .draw_map_editor(&self.theme, &self.map, &self.brush);
             }
             Mode::Exiting => (),
         }
@@ -1,12 +1,32 @@
-use std::collections::HashMap;
+pub mod history;
+pub mod selection;
+pub mod symmetry;
+
+use std::collections::{HashMap, HashSet};
 
 use anyhow::Result;
 use hexx::Hex;
 use macroquad::prelude::*;
 
 use crate::game::{
+    brush::{
+        history::History,
+        selection::{Clipboard, SelectMode, Selection},
+        symmetry::Symmetry,
+    },
     events::{Event, EventS, EventT, Events, MouseButton2},
-    map::{Map, h2q, q2h, tile::Tile},
+    map::{
+        Map,
+        background::Background,
+        edge::{Edge, EdgeFeature, EdgeKind},
+        generator::{Generation, GeneratorKind},
+        grid::{Grid, GridKind},
+        lint::{Issue, Severity},
+        merge::MergePolicy,
+        stamp::{STAMP_DIR, Stamp},
+        terrain::Terrain,
+        tile::Tile,
+    },
     theme::{Theme, ThemeColor},
 };
 
@@ -17,6 +37,8 @@ pub enum BrushEvent {
     PickHalf,
     PickLarge,
     PickFull,
+    /// Next tile shape that is not one of the presets
+    PickMask,
     RotateClockwise,
     RotateAntiClockwise,
     CloneTile,
@@ -25,16 +47,179 @@ pub enum BrushEvent {
     SizeUp,
     SizeDown,
     InsertWalls,
+    NextLayer,
+    PreviousLayer,
+    ToggleLayerVisible,
+    ToggleLayerGmOnly,
+    LayerOpacityUp,
+    LayerOpacityDown,
+    NextTool,
+    PickTerrain(Terrain),
+    PickEdge(EdgeKind),
+    PickElevation(ElevationMode),
+    NextGrid,
+    RotateBackground(i8),
+    PickGenerator(GeneratorKind),
+    Undo,
+    Redo,
+    PickSelect(SelectMode),
+    Copy,
+    Paste,
+    Mirror,
+    NextShape,
+    ToggleAutoWalls,
+    NextSymmetry,
+    SymmetryCenter,
+    SaveStamp,
+    NextStamp,
+    PreviousStamp,
+    PickMergePolicy(MergePolicy),
+    /// Show or hide the issues found on the map
+    Lint,
 }
 
 impl Event for BrushEvent {}
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BrushTool {
+    Tiles,
+    /// Flood fill the connected cells holding the same tile, or no tile
+    Fill,
+    Terrain,
+    Edges,
+    Elevation,
+    /// Move, scale and turn the background image
+    Background,
+    /// Align the grid to two cell centers clicked on the background
+    Calibrate,
+    /// Fill the brush area with a generated dungeon or cave
+    Generate,
+    /// Select cells, then copy, turn, mirror and paste their tiles
+    Select,
+    /// Place saved groups of tiles, turned and mirrored
+    Stamp,
+    /// Place another map file, dropped on the window, into this one
+    Merge,
+}
+
+/// Cells painted by the tile, terrain and elevation brushes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BrushShape {
+    /// Every cell within `brush_size`
+    Area,
+    /// Only the cells `brush_size` away
+    Ring,
+    /// From where the button was pressed to where it is released
+    Line,
+    /// A 60° wedge from where the button was pressed towards the cursor
+    Cone,
+    /// Corners placed with clicks, closed by clicking the first one again
+    Polygon,
+}
+
+impl BrushShape {
+    pub fn next(&self) -> Self {
+        match self {
+            BrushShape::Area => BrushShape::Ring,
+            BrushShape::Ring => BrushShape::Line,
+            BrushShape::Line => BrushShape::Cone,
+            BrushShape::Cone => BrushShape::Polygon,
+            BrushShape::Polygon => BrushShape::Area,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ElevationMode {
+    Raise,
+    Lower,
+    Flatten,
+}
+
+impl BrushTool {
+    pub fn next(&self) -> Self {
+        match self {
+            BrushTool::Tiles => BrushTool::Fill,
+            BrushTool::Fill => BrushTool::Terrain,
+            BrushTool::Terrain => BrushTool::Edges,
+            BrushTool::Edges => BrushTool::Elevation,
+            BrushTool::Elevation => BrushTool::Background,
+            BrushTool::Background => BrushTool::Calibrate,
+            BrushTool::Calibrate => BrushTool::Generate,
+            BrushTool::Generate => BrushTool::Select,
+            BrushTool::Select => BrushTool::Stamp,
+            BrushTool::Stamp => BrushTool::Merge,
+            BrushTool::Merge => BrushTool::Tiles,
+        }
+    }
+}
+
+/// Cells a fill would reach, and what they were found for
+struct FillPreview {
+    start: Hex,
+    layer: usize,
+    /// `Chunks::revision` of the layer's tiles
+    revision: u64,
+    grid: GridKind,
+    hexes: Option<Vec<Hex>>,
+}
+
 pub struct Brush {
     to_fade: HashMap<Hex, f32>,
     fade_factor: f32,
+    tool: BrushTool,
     brush: Tile,
+    terrain: Terrain,
+    edge_kind: EdgeKind,
+    hoovered_edge: Option<Edge>,
+    elevation_mode: ElevationMode,
+    /// Hexes already changed by the current elevation stroke, and the flatten target
+    elevation_stroke: HashSet<Hex>,
+    flatten_to: Option<i32>,
+    /// Last mouse position while dragging the background
+    drag_from: Option<Vec2>,
+    /// Cells between the two calibration clicks, and the first click
+    calibration_span: u32,
+    calibration_start: Option<Vec2>,
+    insert_held: bool,
+    remove_held: bool,
+    brush_shape: BrushShape,
+    /// Where a line or cone started, and whether it erases
+    shape_start: Option<(Hex, bool)>,
+    polygon: Vec<Hex>,
+    /// Re-wall the cells around the floor painted by each stroke
+    auto_walls: bool,
+    symmetry: Symmetry,
+    symmetry_center: Hex,
+    /// Stroke changes already walled, and the walls painted or cells erased by hand during the
+    /// stroke
+    walled: usize,
+    stroke_walls: HashSet<Hex>,
+    generator: GeneratorKind,
+    seed: u64,
+    /// World position of the mouse
+    mouse: Vec2,
+    history: History,
+    select_mode: SelectMode,
+    selection: Selection,
+    clipboard: Clipboard,
+    /// Whether clicks paste the clipboard instead of selecting
+    pasting: bool,
+    /// Stamps saved for the current grid, the picked one, and its tiles as placed
+    stamps: Vec<Stamp>,
+    stamp: usize,
+    stamp_tiles: Clipboard,
+    merge_source: Option<Map>,
+    merge_policy: MergePolicy,
+    /// Issues highlighted on the map, found again after each change while shown
+    lint: Option<Vec<Issue>>,
     brush_size: u32,
     brush_max_size: u32,
+    /// Fills reaching more cells than this are cancelled, as they leaked into the void
+    fill_max_size: usize,
+    /// Kept until the cursor or the tiles change, as filling is too slow to redo every frame
+    fill_preview: Option<FillPreview>,
+    opacity_delta: f32,
     brush_events: Events<BrushEvent>,
 }
 
@@ -43,9 +228,46 @@ impl Default for Brush {
         Self {
             to_fade: HashMap::new(),
             fade_factor: 5.,
+            tool: BrushTool::Tiles,
             brush: Tile::Empty,
+            terrain: Terrain::Difficult,
+            edge_kind: EdgeKind::Door,
+            hoovered_edge: None,
+            elevation_mode: ElevationMode::Raise,
+            elevation_stroke: HashSet::new(),
+            flatten_to: None,
+            drag_from: None,
+            calibration_span: 5,
+            calibration_start: None,
+            insert_held: false,
+            remove_held: false,
+            brush_shape: BrushShape::Area,
+            shape_start: None,
+            polygon: Vec::new(),
+            auto_walls: true,
+            symmetry: Symmetry::Off,
+            symmetry_center: Hex::ZERO,
+            walled: 0,
+            stroke_walls: HashSet::new(),
+            generator: GeneratorKind::RoomsAndCorridors,
+            seed: 0,
+            mouse: Vec2::ZERO,
+            history: History::default(),
+            select_mode: SelectMode::Box,
+            selection: Selection::default(),
+            clipboard: Clipboard::default(),
+            pasting: false,
+            stamps: Vec::new(),
+            stamp: 0,
+            stamp_tiles: Clipboard::default(),
+            merge_source: None,
+            merge_policy: MergePolicy::KeepOurs,
+            lint: None,
             brush_size: 0,
             brush_max_size: 16,
+            fill_max_size: 4096,
+            fill_preview: None,
+            opacity_delta: 0.125,
             brush_events: Events::from([
                 (
                     BrushEvent::PickEmpty,
@@ -67,6 +289,10 @@ impl Default for Brush {
                     BrushEvent::PickFull,
                     vec![vec![(EventS::JustPressed, EventT::Keyboard(KeyCode::Key5))]],
                 ),
+                (
+                    BrushEvent::PickMask,
+                    vec![vec![(EventS::JustPressed, EventT::Keyboard(KeyCode::Key6))]],
+                ),
                 (
                     BrushEvent::RotateClockwise,
                     vec![
@@ -120,6 +346,214 @@ impl Default for Brush {
                     BrushEvent::InsertWalls,
                     vec![vec![(EventS::JustPressed, EventT::Keyboard(KeyCode::I))]],
                 ),
+                (
+                    BrushEvent::NextLayer,
+                    vec![vec![(
+                        EventS::JustPressed,
+                        EventT::Keyboard(KeyCode::PageUp),
+                    )]],
+                ),
+                (
+                    BrushEvent::PreviousLayer,
+                    vec![vec![(
+                        EventS::JustPressed,
+                        EventT::Keyboard(KeyCode::PageDown),
+                    )]],
+                ),
+                (
+                    BrushEvent::ToggleLayerVisible,
+                    vec![vec![(EventS::JustPressed, EventT::Keyboard(KeyCode::V))]],
+                ),
+                (
+                    BrushEvent::ToggleLayerGmOnly,
+                    vec![vec![(EventS::JustPressed, EventT::Keyboard(KeyCode::G))]],
+                ),
+                (
+                    BrushEvent::LayerOpacityUp,
+                    vec![vec![(
+                        EventS::JustPressed,
+                        EventT::Keyboard(KeyCode::RightBracket),
+                    )]],
+                ),
+                (
+                    BrushEvent::LayerOpacityDown,
+                    vec![vec![(
+                        EventS::JustPressed,
+                        EventT::Keyboard(KeyCode::LeftBracket),
+                    )]],
+                ),
+                (
+                    BrushEvent::NextTool,
+                    vec![vec![(EventS::JustPressed, EventT::Keyboard(KeyCode::T))]],
+                ),
+                (
+                    BrushEvent::RotateBackground(-1),
+                    vec![vec![(EventS::IsPressed, EventT::Keyboard(KeyCode::Key1))]],
+                ),
+                (
+                    BrushEvent::RotateBackground(1),
+                    vec![vec![(EventS::IsPressed, EventT::Keyboard(KeyCode::Key2))]],
+                ),
+                (
+                    BrushEvent::Undo,
+                    vec![vec![
+                        (EventS::IsPressed, EventT::Keyboard(KeyCode::LeftControl)),
+                        (EventS::JustPressed, EventT::Keyboard(KeyCode::Z)),
+                    ]],
+                ),
+                (
+                    BrushEvent::Redo,
+                    vec![vec![
+                        (EventS::IsPressed, EventT::Keyboard(KeyCode::LeftControl)),
+                        (EventS::IsPressed, EventT::Keyboard(KeyCode::LeftShift)),
+                        (EventS::JustPressed, EventT::Keyboard(KeyCode::Z)),
+                    ]],
+                ),
+                (
+                    BrushEvent::Copy,
+                    vec![vec![
+                        (EventS::IsPressed, EventT::Keyboard(KeyCode::LeftControl)),
+                        (EventS::JustPressed, EventT::Keyboard(KeyCode::C)),
+                    ]],
+                ),
+                (
+                    BrushEvent::Paste,
+                    vec![vec![
+                        (EventS::IsPressed, EventT::Keyboard(KeyCode::LeftControl)),
+                        (EventS::JustPressed, EventT::Keyboard(KeyCode::V)),
+                    ]],
+                ),
+                (
+                    BrushEvent::Mirror,
+                    vec![vec![(EventS::JustPressed, EventT::Keyboard(KeyCode::H))]],
+                ),
+                (
+                    BrushEvent::NextShape,
+                    vec![vec![(EventS::JustPressed, EventT::Keyboard(KeyCode::B))]],
+                ),
+                (
+                    BrushEvent::ToggleAutoWalls,
+                    vec![vec![(EventS::JustPressed, EventT::Keyboard(KeyCode::U))]],
+                ),
+                (
+                    BrushEvent::NextSymmetry,
+                    vec![vec![(EventS::JustPressed, EventT::Keyboard(KeyCode::R))]],
+                ),
+                (
+                    BrushEvent::SymmetryCenter,
+                    vec![vec![(EventS::JustPressed, EventT::Keyboard(KeyCode::K))]],
+                ),
+                (
+                    BrushEvent::SaveStamp,
+                    vec![vec![(EventS::JustPressed, EventT::Keyboard(KeyCode::J))]],
+                ),
+                (
+                    BrushEvent::NextStamp,
+                    vec![vec![(EventS::JustPressed, EventT::Keyboard(KeyCode::N))]],
+                ),
+                (
+                    BrushEvent::PreviousStamp,
+                    vec![vec![
+                        (EventS::IsPressed, EventT::Keyboard(KeyCode::LeftShift)),
+                        (EventS::JustPressed, EventT::Keyboard(KeyCode::N)),
+                    ]],
+                ),
+                (
+                    BrushEvent::PickMergePolicy(MergePolicy::KeepOurs),
+                    vec![vec![(EventS::JustPressed, EventT::Keyboard(KeyCode::Key1))]],
+                ),
+                (
+                    BrushEvent::PickMergePolicy(MergePolicy::TakeTheirs),
+                    vec![vec![(EventS::JustPressed, EventT::Keyboard(KeyCode::Key2))]],
+                ),
+                (
+                    BrushEvent::PickMergePolicy(MergePolicy::UnionWalls),
+                    vec![vec![(EventS::JustPressed, EventT::Keyboard(KeyCode::Key3))]],
+                ),
+                (
+                    BrushEvent::Lint,
+                    vec![vec![(EventS::JustPressed, EventT::Keyboard(KeyCode::F5))]],
+                ),
+                (
+                    BrushEvent::NextGrid,
+                    vec![vec![(EventS::JustPressed, EventT::Keyboard(KeyCode::Y))]],
+                ),
+                (
+                    BrushEvent::PickEdge(EdgeKind::Door),
+                    vec![vec![(EventS::JustPressed, EventT::Keyboard(KeyCode::Key1))]],
+                ),
+                (
+                    BrushEvent::PickEdge(EdgeKind::Window),
+                    vec![vec![(EventS::JustPressed, EventT::Keyboard(KeyCode::Key2))]],
+                ),
+                (
+                    BrushEvent::PickEdge(EdgeKind::SecretDoor),
+                    vec![vec![(EventS::JustPressed, EventT::Keyboard(KeyCode::Key3))]],
+                ),
+                (
+                    BrushEvent::PickEdge(EdgeKind::Portcullis),
+                    vec![vec![(EventS::JustPressed, EventT::Keyboard(KeyCode::Key4))]],
+                ),
+                (
+                    BrushEvent::PickElevation(ElevationMode::Raise),
+                    vec![vec![(EventS::JustPressed, EventT::Keyboard(KeyCode::Key1))]],
+                ),
+                (
+                    BrushEvent::PickElevation(ElevationMode::Lower),
+                    vec![vec![(EventS::JustPressed, EventT::Keyboard(KeyCode::Key2))]],
+                ),
+                (
+                    BrushEvent::PickElevation(ElevationMode::Flatten),
+                    vec![vec![(EventS::JustPressed, EventT::Keyboard(KeyCode::Key3))]],
+                ),
+                (
+                    BrushEvent::PickGenerator(GeneratorKind::RoomsAndCorridors),
+                    vec![vec![(EventS::JustPressed, EventT::Keyboard(KeyCode::Key1))]],
+                ),
+                (
+                    BrushEvent::PickGenerator(GeneratorKind::Caves),
+                    vec![vec![(EventS::JustPressed, EventT::Keyboard(KeyCode::Key2))]],
+                ),
+                (
+                    BrushEvent::PickGenerator(GeneratorKind::RandomWalk),
+                    vec![vec![(EventS::JustPressed, EventT::Keyboard(KeyCode::Key3))]],
+                ),
+                (
+                    BrushEvent::PickSelect(SelectMode::Box),
+                    vec![vec![(EventS::JustPressed, EventT::Keyboard(KeyCode::Key1))]],
+                ),
+                (
+                    BrushEvent::PickSelect(SelectMode::Lasso),
+                    vec![vec![(EventS::JustPressed, EventT::Keyboard(KeyCode::Key2))]],
+                ),
+                (
+                    BrushEvent::PickSelect(SelectMode::Wand),
+                    vec![vec![(EventS::JustPressed, EventT::Keyboard(KeyCode::Key3))]],
+                ),
+                (
+                    BrushEvent::PickTerrain(Terrain::Normal),
+                    vec![vec![(EventS::JustPressed, EventT::Keyboard(KeyCode::Key1))]],
+                ),
+                (
+                    BrushEvent::PickTerrain(Terrain::Difficult),
+                    vec![vec![(EventS::JustPressed, EventT::Keyboard(KeyCode::Key2))]],
+                ),
+                (
+                    BrushEvent::PickTerrain(Terrain::Water),
+                    vec![vec![(EventS::JustPressed, EventT::Keyboard(KeyCode::Key3))]],
+                ),
+                (
+                    BrushEvent::PickTerrain(Terrain::Lava),
+                    vec![vec![(EventS::JustPressed, EventT::Keyboard(KeyCode::Key4))]],
+                ),
+                (
+                    BrushEvent::PickTerrain(Terrain::Glass),
+                    vec![vec![(EventS::JustPressed, EventT::Keyboard(KeyCode::Key5))]],
+                ),
+                (
+                    BrushEvent::PickTerrain(Terrain::Fog),
+                    vec![vec![(EventS::JustPressed, EventT::Keyboard(KeyCode::Key6))]],
+                ),
             ]),
         }
     }
@@ -129,26 +563,252 @@ impl Brush {
     pub async fn handle_events(&mut self, map: &mut Map, camera: &Camera2D) -> Result<()> {
         self.brush_events.update();
 
-        if self.brush_events.pop(&BrushEvent::PickEmpty) {
-            self.brush = Tile::Empty;
+        if self.brush_events.pop(&BrushEvent::NextTool) {
+            self.tool = self.tool.next();
+            self.shape_start = None;
+            self.polygon.clear();
+            // Picks up stamps saved or renamed since
+            if self.tool == BrushTool::Stamp {
+                self.stamps = Stamp::load_all(STAMP_DIR, map.grid.kind);
+                self.pick_stamp(0);
+            }
+        }
+        if self.brush_events.pop(&BrushEvent::ToggleAutoWalls) {
+            self.auto_walls = !self.auto_walls;
         }
-        if self.brush_events.pop(&BrushEvent::PickSmall) {
-            self.brush = Tile::Small { rotation: 0 };
+        if self.brush_events.pop(&BrushEvent::NextSymmetry) {
+            self.symmetry = self.symmetry.next(&map.grid);
         }
-        if self.brush_events.pop(&BrushEvent::PickHalf) {
-            self.brush = Tile::Half { rotation: 0 };
+        if self.brush_events.pop(&BrushEvent::NextShape) {
+            self.brush_shape = self.brush_shape.next();
+            self.shape_start = None;
+            self.polygon.clear();
         }
-        if self.brush_events.pop(&BrushEvent::PickLarge) {
-            self.brush = Tile::Large { rotation: 0 };
+        if self.brush_events.pop(&BrushEvent::Lint) {
+            self.lint = match self.lint {
+                Some(_) => None,
+                None => Some(map.lint()),
+            };
         }
-        if self.brush_events.pop(&BrushEvent::PickFull) {
-            self.brush = Tile::Full;
+        // The map changes once a stroke ends, or on undo and redo
+        let mut relint = self.insert_held || self.remove_held;
+        // Ctrl+Shift+Z also matches the undo binding
+        if self.brush_events.pop(&BrushEvent::Redo) {
+            self.brush_events.pop(&BrushEvent::Undo);
+            self.history.redo(map);
+            relint = true;
+        }
+        if self.brush_events.pop(&BrushEvent::Undo) {
+            self.history.undo(map);
+            relint = true;
+        }
+        // Ctrl+V also matches the plain V binding
+        if self.brush_events.contains(&BrushEvent::Paste) {
+            self.brush_events.pop(&BrushEvent::ToggleLayerVisible);
+        }
+
+        match self.tool {
+            BrushTool::Tiles | BrushTool::Fill => {
+                if self.brush_events.pop(&BrushEvent::PickEmpty) {
+                    self.brush = Tile::Empty;
+                }
+                if self.brush_events.pop(&BrushEvent::PickSmall) {
+                    self.brush = Tile::Small { rotation: 0 };
+                }
+                if self.brush_events.pop(&BrushEvent::PickHalf) {
+                    self.brush = Tile::Half { rotation: 0 };
+                }
+                if self.brush_events.pop(&BrushEvent::PickLarge) {
+                    self.brush = Tile::Large { rotation: 0 };
+                }
+                if self.brush_events.pop(&BrushEvent::PickFull) {
+                    self.brush = Tile::Full;
+                }
+                if self.brush_events.pop(&BrushEvent::PickMask) {
+                    let masks = Vec::from_iter(
+                        map.grid
+                            .all_tiles()
+                            .into_iter()
+                            .filter(|tile| matches!(tile, Tile::Mask { .. })),
+                    );
+                    let next = masks
+                        .iter()
+                        .position(|tile| *tile == self.brush)
+                        .map_or(0, |i| i + 1);
+                    if let Some(tile) = masks.get(next % masks.len().max(1)) {
+                        self.brush = *tile;
+                    }
+                }
+            }
+            BrushTool::Terrain => {
+                for terrain in Terrain::ALL {
+                    if self.brush_events.pop(&BrushEvent::PickTerrain(terrain)) {
+                        self.terrain = terrain;
+                    }
+                }
+            }
+            BrushTool::Edges => {
+                for kind in [
+                    EdgeKind::Door,
+                    EdgeKind::Window,
+                    EdgeKind::SecretDoor,
+                    EdgeKind::Portcullis,
+                ] {
+                    if self.brush_events.pop(&BrushEvent::PickEdge(kind)) {
+                        self.edge_kind = kind;
+                    }
+                }
+            }
+            BrushTool::Elevation => {
+                for mode in [
+                    ElevationMode::Raise,
+                    ElevationMode::Lower,
+                    ElevationMode::Flatten,
+                ] {
+                    if self.brush_events.pop(&BrushEvent::PickElevation(mode)) {
+                        self.elevation_mode = mode;
+                    }
+                }
+            }
+            BrushTool::Background => {
+                let mouse = camera.screen_to_world(mouse_position().into());
+                if let Some(background) = &mut map.background {
+                    if self.brush_events.pop(&BrushEvent::SizeUp) {
+                        background.scale_around(mouse, 1.01);
+                    }
+                    if self.brush_events.pop(&BrushEvent::SizeDown) {
+                        background.scale_around(mouse, 1. / 1.01);
+                    }
+                    for delta in [-1, 1] {
+                        if self.brush_events.pop(&BrushEvent::RotateBackground(delta)) {
+                            background.rotate_around(mouse, (delta as f32).to_radians() / 4.);
+                        }
+                    }
+                }
+            }
+            BrushTool::Calibrate => {
+                if self.brush_events.pop(&BrushEvent::SizeUp) {
+                    self.calibration_span += 1;
+                }
+                if self.brush_events.pop(&BrushEvent::SizeDown) {
+                    self.calibration_span = (self.calibration_span - 1).max(1);
+                }
+            }
+            BrushTool::Generate => {
+                for kind in GeneratorKind::ALL {
+                    if self.brush_events.pop(&BrushEvent::PickGenerator(kind)) {
+                        self.generator = kind;
+                    }
+                }
+            }
+            BrushTool::Select => {
+                for mode in SelectMode::ALL {
+                    if self.brush_events.pop(&BrushEvent::PickSelect(mode)) {
+                        self.select_mode = mode;
+                    }
+                }
+                if self.brush_events.pop(&BrushEvent::Copy) {
+                    let tiles = map.active_tiles();
+                    self.clipboard = Clipboard::copy(&map.grid, &self.selection.cells, |hex| {
+                        tiles.get(&hex).copied()
+                    });
+                }
+                if self.brush_events.pop(&BrushEvent::Paste) {
+                    self.pasting = !self.clipboard.is_empty();
+                }
+                if self.brush_events.pop(&BrushEvent::RotateClockwise) {
+                    self.clipboard.rotate(&map.grid, 1);
+                }
+                if self.brush_events.pop(&BrushEvent::RotateAntiClockwise) {
+                    self.clipboard.rotate(&map.grid, -1);
+                }
+                if self.brush_events.pop(&BrushEvent::Mirror) {
+                    self.clipboard.mirror(&map.grid);
+                }
+                if self.brush_events.pop(&BrushEvent::SaveStamp) {
+                    let tiles = map.active_tiles();
+                    let copied = Clipboard::copy(&map.grid, &self.selection.cells, |hex| {
+                        tiles.get(&hex).copied()
+                    });
+                    let mut stamp = Stamp {
+                        name: String::new(),
+                        grid: map.grid.kind,
+                        tiles: copied.tiles,
+                    };
+                    if stamp.tiles.is_empty() {
+                        warn!("Nothing selected to save as a stamp");
+                    } else if let Err(error) = stamp.save(STAMP_DIR, &map.active_layer().name) {
+                        warn!("Saving the stamp failed: {error}");
+                    } else {
+                        info!("Saved stamp {}", stamp.name);
+                    }
+                }
+            }
+            BrushTool::Stamp => {
+                // Shift+N also matches the next stamp binding
+                if self.brush_events.pop(&BrushEvent::PreviousStamp) {
+                    self.brush_events.pop(&BrushEvent::NextStamp);
+                    self.pick_stamp(self.stamp + self.stamps.len().max(1) - 1);
+                }
+                if self.brush_events.pop(&BrushEvent::NextStamp) {
+                    self.pick_stamp(self.stamp + 1);
+                }
+                if self.brush_events.pop(&BrushEvent::RotateClockwise) {
+                    self.stamp_tiles.rotate(&map.grid, 1);
+                }
+                if self.brush_events.pop(&BrushEvent::RotateAntiClockwise) {
+                    self.stamp_tiles.rotate(&map.grid, -1);
+                }
+                if self.brush_events.pop(&BrushEvent::Mirror) {
+                    self.stamp_tiles.mirror(&map.grid);
+                }
+            }
+            BrushTool::Merge => {
+                for policy in MergePolicy::ALL {
+                    if self.brush_events.pop(&BrushEvent::PickMergePolicy(policy)) {
+                        self.merge_policy = policy;
+                    }
+                }
+            }
+        }
+        if self.brush_events.pop(&BrushEvent::NextGrid) {
+            map.set_grid(map.grid.kind.next(), map.grid.size);
+            // Shapes and axes of the old grid may not exist on the new one
+            if !map.tile_variants.contains_key(&self.brush) {
+                self.brush = Tile::Empty;
+            }
+            self.symmetry = Symmetry::Off;
+            self.stamps = Stamp::load_all(STAMP_DIR, map.grid.kind);
+            self.pick_stamp(0);
+        }
+
+        for file in get_dropped_files() {
+            let image = file
+                .path
+                .map(|path| path.to_string_lossy().to_string())
+                .unwrap_or_default();
+            if self.tool == BrushTool::Merge {
+                match Map::from_file(&image) {
+                    Ok(source) => self.merge_source = Some(source),
+                    Err(error) => warn!("Loading {image} to merge failed: {error}"),
+                }
+                continue;
+            }
+            let Some(bytes) = file.bytes.or_else(|| std::fs::read(&image).ok()) else {
+                continue;
+            };
+            if let Ok(texture) = Image::from_file_with_format(&bytes, None) {
+                let mut background =
+                    Background::new(&image, camera.screen_to_world(mouse_position().into()));
+                background.texture = Some(Texture2D::from_image(&texture));
+                map.background = Some(background);
+            }
         }
         if self.brush_events.pop(&BrushEvent::RotateClockwise) {
-            self.brush.rotate(1);
+            self.brush = map.grid.rotate_tile(self.brush, 1);
         }
         if self.brush_events.pop(&BrushEvent::RotateAntiClockwise) {
-            self.brush.rotate(-1);
+            self.brush = map.grid.rotate_tile(self.brush, -1);
         }
         if self.brush_events.pop(&BrushEvent::SizeUp) {
             if self.brush_size == self.brush_max_size {
@@ -161,41 +821,238 @@ impl Brush {
             self.brush_size = self.brush_size.saturating_sub(1);
         }
 
-        if !self.brush.is_empty_or_full() {
+        if !self.is_area_brush() {
             self.brush_size = 0;
         }
 
+        if self.brush_events.pop(&BrushEvent::NextLayer) {
+            map.select_layer(1);
+        }
+        if self.brush_events.pop(&BrushEvent::PreviousLayer) {
+            map.select_layer(-1);
+        }
+        if self.brush_events.pop(&BrushEvent::ToggleLayerVisible) {
+            let layer = map.active_layer_mut();
+            layer.visible = !layer.visible;
+        }
+        if self.brush_events.pop(&BrushEvent::ToggleLayerGmOnly) {
+            let layer = map.active_layer_mut();
+            layer.gm_only = !layer.gm_only;
+        }
+        if self.brush_events.pop(&BrushEvent::LayerOpacityUp) {
+            map.active_layer_mut().change_opacity(self.opacity_delta);
+        }
+        if self.brush_events.pop(&BrushEvent::LayerOpacityDown) {
+            map.active_layer_mut().change_opacity(-self.opacity_delta);
+        }
+
         let hoovered_hex = map
-            .hex_layout
-            .world_pos_to_hex(q2h(camera.screen_to_world(mouse_position().into())));
+            .grid
+            .world_to_cell(camera.screen_to_world(mouse_position().into()));
+        let hoovered_edge =
+            Edge::from_world_pos(&map.grid, camera.screen_to_world(mouse_position().into()));
 
-        if self.brush_events.pop(&BrushEvent::CloneTile)
-            && let Some(tile) = map.tiles.get(&hoovered_hex)
-        {
-            self.brush = *tile;
+        if self.brush_events.pop(&BrushEvent::SymmetryCenter) {
+            self.symmetry_center = hoovered_hex;
         }
-        if self.brush_events.pop(&BrushEvent::Insert) {
-            if self.brush.is_empty_or_full() {
-                for hex in hoovered_hex.range(self.brush_size) {
-                    map.tiles.insert(hex, self.brush);
+        if self.brush_events.pop(&BrushEvent::CloneTile) {
+            match self.tool {
+                BrushTool::Edges => {
+                    if let Some(feature) = map.edges.get(&hoovered_edge) {
+                        self.edge_kind = feature.kind;
+                    }
                 }
-            } else {
-                map.tiles.insert(hoovered_hex, self.brush);
+                BrushTool::Tiles | BrushTool::Fill => {
+                    if let Some(tile) = map.active_tiles().get(&hoovered_hex) {
+                        self.brush = *tile;
+                    }
+                }
+                BrushTool::Terrain => self.terrain = map.terrain_at(hoovered_hex),
+                // Flatten takes its height from where the stroke starts
+                BrushTool::Elevation => self.elevation_mode = ElevationMode::Flatten,
+                // A new seed for the next generation
+                BrushTool::Generate => {
+                    self.seed = (miniquad::date::now() * 1000.) as u64;
+                }
+                BrushTool::Background
+                | BrushTool::Calibrate
+                | BrushTool::Select
+                | BrushTool::Stamp
+                | BrushTool::Merge => (),
             }
         }
-        if self.brush_events.pop(&BrushEvent::Remove) {
-            if self.brush.is_empty_or_full() {
-                for hex in hoovered_hex.range(self.brush_size) {
-                    map.tiles.remove(&hex);
+        let insert = self.brush_events.pop(&BrushEvent::Insert);
+        let just_inserted = insert && !self.insert_held;
+        self.insert_held = insert;
+        let remove = self.brush_events.pop(&BrushEvent::Remove);
+        let just_removed = remove && !self.remove_held;
+        self.remove_held = remove;
+        if !insert {
+            self.elevation_stroke.clear();
+            self.flatten_to = None;
+            self.drag_from = None;
+            self.selection.finish(&map.grid, self.select_mode);
+        }
+        if insert {
+            match self.tool {
+                BrushTool::Tiles | BrushTool::Terrain | BrushTool::Elevation => {
+                    self.stroke(map, hoovered_hex, false, just_inserted);
+                }
+                BrushTool::Fill => {
+                    if just_inserted {
+                        match self.fill_hexes(map, hoovered_hex) {
+                            Some(hexes) => {
+                                for hex in hexes {
+                                    self.paint_tile(map, hex, Some(self.brush));
+                                }
+                            }
+                            None => warn!("Fill cancelled: more than {} cells", self.fill_max_size),
+                        }
+                    }
+                }
+                BrushTool::Edges => {
+                    let kind = self.edge_kind;
+                    map.edges
+                        .entry(hoovered_edge)
+                        .and_modify(|feature| feature.kind = kind)
+                        .or_insert(EdgeFeature::new(kind));
+                }
+                BrushTool::Background => {
+                    let mouse = camera.screen_to_world(mouse_position().into());
+                    if let Some(background) = &mut map.background
+                        && let Some(from) = self.drag_from
+                    {
+                        background.offset += mouse - from;
+                    }
+                    self.drag_from = Some(mouse);
+                }
+                BrushTool::Calibrate => {
+                    if just_inserted {
+                        let mouse = camera.screen_to_world(mouse_position().into());
+                        match self.calibration_start.take() {
+                            None => self.calibration_start = Some(mouse),
+                            Some(start) => {
+                                let (hex_a, hex_b) =
+                                    self.calibration_cells(&map.grid, start, mouse);
+                                map.calibrate_grid((hex_a, start), (hex_b, mouse));
+                            }
+                        }
+                    }
+                }
+                BrushTool::Generate => {
+                    if just_inserted {
+                        let generation = Generation {
+                            kind: self.generator,
+                            seed: self.seed,
+                            center: hoovered_hex,
+                            radius: self.brush_size,
+                        };
+                        for hex in map.grid.range(generation.center, generation.radius) {
+                            self.history.set(map, hex, None);
+                        }
+                        for hex in generation.floor(&map.grid) {
+                            self.history.set(map, hex, Some(Tile::Empty));
+                        }
+                        map.generations.push(generation);
+                        self.insert_walls(map);
+                    }
+                }
+                BrushTool::Select => {
+                    if self.pasting {
+                        if just_inserted {
+                            let placed = Vec::from_iter(self.clipboard.placed(hoovered_hex));
+                            for (hex, tile) in placed {
+                                self.history.set(map, hex, Some(tile));
+                            }
+                        }
+                    } else if self.select_mode == SelectMode::Wand {
+                        if just_inserted {
+                            let tiles = map.active_tiles();
+                            self.selection
+                                .wand(&map.grid, hoovered_hex, |hex| tiles.get(&hex).copied());
+                        }
+                    } else {
+                        self.selection
+                            .drag(camera.screen_to_world(mouse_position().into()));
+                    }
+                }
+                BrushTool::Stamp => {
+                    if just_inserted {
+                        let placed = Vec::from_iter(self.stamp_tiles.placed(hoovered_hex));
+                        for (hex, tile) in placed {
+                            self.history.set(map, hex, Some(tile));
+                        }
+                    }
+                }
+                BrushTool::Merge => {
+                    if just_inserted && let Some(source) = &self.merge_source {
+                        let before =
+                            Vec::from_iter(map.layers.iter().map(|layer| layer.tiles.clone()));
+                        match map.merge(source, hoovered_hex, self.merge_policy) {
+                            Ok(conflicts) => {
+                                info!(
+                                    "Merged {} with {conflicts} conflicts",
+                                    source.current_map_file
+                                );
+                                // Only the tiles are undone, on every layer the merge wrote
+                                self.history.record(&before, map);
+                                // The merge walled its own seam already
+                                self.walled = self.history.stroke().len();
+                            }
+                            Err(error) => warn!("Merge failed: {error}"),
+                        }
+                    }
+                }
+            }
+        }
+        if remove {
+            match self.tool {
+                BrushTool::Tiles | BrushTool::Terrain | BrushTool::Elevation => {
+                    self.stroke(map, hoovered_hex, true, just_removed);
+                }
+                BrushTool::Edges => {
+                    map.edges.remove(&hoovered_edge);
+                }
+                BrushTool::Fill
+                | BrushTool::Background
+                | BrushTool::Generate
+                | BrushTool::Stamp => (),
+                BrushTool::Merge => self.merge_source = None,
+                BrushTool::Calibrate => self.calibration_start = None,
+                BrushTool::Select => {
+                    if self.pasting {
+                        self.pasting = false;
+                    } else {
+                        self.selection.cells.clear();
+                    }
                 }
-            } else {
-                map.tiles.remove(&hoovered_hex);
             }
         }
 
+        // Lines and cones are painted once the button that started them is released
+        if let Some((start, erase)) = self.shape_start
+            && !(if erase { remove } else { insert })
+        {
+            let hexes = self.brush_hexes(&map.grid, hoovered_hex);
+            self.shape_start = None;
+            self.paint(map, start, hexes, erase);
+        }
+
         if self.brush_events.pop(&BrushEvent::InsertWalls) {
             self.insert_walls(map);
         }
+        if self.auto_walls {
+            self.follow_floor(map);
+        }
+        // A stroke lasts as long as a mouse button is held
+        if !insert && !remove {
+            self.history.end_stroke();
+            self.walled = 0;
+            self.stroke_walls.clear();
+            if relint && let Some(issues) = &mut self.lint {
+                *issues = map.lint();
+            }
+        }
 
         Ok(())
     }
@@ -206,105 +1063,638 @@ impl Brush {
             *alpha > 0.
         });
 
-        let hoovered_hex = map
-            .hex_layout
-            .world_pos_to_hex(q2h(camera.screen_to_world(mouse_position().into())));
+        self.mouse = camera.screen_to_world(mouse_position().into());
+        let hoovered_hex = map.grid.world_to_cell(self.mouse);
 
-        if self.brush.is_empty_or_full() {
-            for hex in hoovered_hex.range(self.brush_size) {
-                self.to_fade.insert(hex, 1.);
+        self.hoovered_edge = None;
+        if self.tool == BrushTool::Fill {
+            self.refresh_fill_preview(map, hoovered_hex);
+            let preview = self.fill_preview.as_ref();
+            for hex in preview
+                .and_then(|preview| preview.hexes.as_ref())
+                .into_iter()
+                .flatten()
+            {
+                self.to_fade.insert(*hex, 1.);
             }
+        } else if self.tool == BrushTool::Edges {
+            self.hoovered_edge = Some(Edge::from_world_pos(
+                &map.grid,
+                camera.screen_to_world(mouse_position().into()),
+            ));
         } else {
-            self.to_fade.insert(hoovered_hex, 1.);
+            // Turned or mirrored copies of a wall tile would not look like the brush
+            let copies = self.tool != BrushTool::Tiles || self.is_area_brush();
+            for hex in self.brush_hexes(&map.grid, hoovered_hex) {
+                if copies && self.paints() {
+                    for hex in self.symmetry.cells(&map.grid, self.symmetry_center, hex) {
+                        self.to_fade.insert(hex, 1.);
+                    }
+                } else {
+                    self.to_fade.insert(hex, 1.);
+                }
+            }
         }
 
         Ok(())
     }
 
+    /// Whether the brush paints `brush_size` ranges or single hexes
+    pub fn is_area_brush(&self) -> bool {
+        match self.tool {
+            BrushTool::Tiles => self.brush.is_empty_or_full(),
+            BrushTool::Terrain | BrushTool::Elevation | BrushTool::Generate => true,
+            BrushTool::Fill
+            | BrushTool::Edges
+            | BrushTool::Background
+            | BrushTool::Calibrate
+            | BrushTool::Select
+            | BrushTool::Stamp
+            | BrushTool::Merge => false,
+        }
+    }
+
+    /// Picks the stamp at `index`, wrapping around, in the turn it was saved with
+    fn pick_stamp(&mut self, index: usize) {
+        self.stamp = index % self.stamps.len().max(1);
+        self.stamp_tiles = Clipboard {
+            tiles: self
+                .stamps
+                .get(self.stamp)
+                .map(|stamp| stamp.tiles.clone())
+                .unwrap_or_default(),
+        };
+    }
+
+    /// Whether the tool's edits follow the symmetry
+    pub fn paints(&self) -> bool {
+        matches!(
+            self.tool,
+            BrushTool::Tiles | BrushTool::Fill | BrushTool::Terrain | BrushTool::Elevation
+        )
+    }
+
+    /// The cell under `start`, and the one `calibration_span` cells from it towards `end`
+    pub fn calibration_cells(&self, grid: &Grid, start: Vec2, end: Vec2) -> (Hex, Hex) {
+        let hex = grid.world_to_cell(start);
+        let center = grid.cell_to_world(hex);
+        let direction = (end - start).normalize_or_zero();
+        let step = grid
+            .neighbors(hex)
+            .into_iter()
+            .max_by(|a, b| {
+                let a = (grid.cell_to_world(*a) - center).normalize().dot(direction);
+                let b = (grid.cell_to_world(*b) - center).normalize().dot(direction);
+                a.total_cmp(&b)
+            })
+            .unwrap_or(hex)
+            - hex;
+        (hex, hex + step * self.calibration_span as i32)
+    }
+
+    /// Cells connected to `start` holding the same tile as it, or `None` past `fill_max_size`
+    pub fn fill_hexes(&self, map: &Map, start: Hex) -> Option<Vec<Hex>> {
+        let tiles = map.active_tiles();
+        let target = tiles.get(&start);
+        let mut seen = HashSet::from([start]);
+        let mut hexes = vec![start];
+        let mut i = 0;
+        while let Some(&hex) = hexes.get(i) {
+            i += 1;
+            for n in map.grid.neighbors(hex) {
+                if tiles.get(&n) == target && seen.insert(n) {
+                    if hexes.len() == self.fill_max_size {
+                        return None;
+                    }
+                    hexes.push(n);
+                }
+            }
+        }
+        Some(hexes)
+    }
+
+    /// Finds `fill_hexes` from `start` again, when the cursor moved or the tiles changed
+    fn refresh_fill_preview(&mut self, map: &Map, start: Hex) {
+        let (layer, revision, grid) = (
+            map.active_layer,
+            map.active_tiles().revision(),
+            map.grid.kind,
+        );
+        let stale = self.fill_preview.as_ref().is_none_or(|preview| {
+            (preview.start, preview.layer, preview.revision, preview.grid)
+                != (start, layer, revision, grid)
+        });
+        if stale {
+            self.fill_preview = Some(FillPreview {
+                start,
+                layer,
+                revision,
+                grid,
+                hexes: self.fill_hexes(map, start),
+            });
+        }
+    }
+
+    /// The shape in use, single hexes and other tools always painting areas
+    pub fn shape(&self) -> BrushShape {
+        match self.tool {
+            BrushTool::Tiles | BrushTool::Terrain | BrushTool::Elevation
+                if self.is_area_brush() =>
+            {
+                self.brush_shape
+            }
+            _ => BrushShape::Area,
+        }
+    }
+
+    pub fn brush_hexes(&self, grid: &Grid, hoovered_hex: Hex) -> Vec<Hex> {
+        if !self.is_area_brush() {
+            return vec![hoovered_hex];
+        }
+        match (self.shape(), self.shape_start) {
+            (BrushShape::Area, _) => grid.range(hoovered_hex, self.brush_size),
+            (BrushShape::Ring, _) => grid.ring(hoovered_hex, self.brush_size),
+            (BrushShape::Line, Some((start, _))) => grid.line(start, hoovered_hex),
+            (BrushShape::Cone, Some((start, _))) => grid.cone(start, hoovered_hex),
+            (BrushShape::Polygon, _) if !self.polygon.is_empty() => {
+                grid.polygon(&[self.polygon.as_slice(), &[hoovered_hex]].concat())
+            }
+            _ => vec![hoovered_hex],
+        }
+    }
+
+    /// Handles a held button of the tile, terrain or elevation brush
+    fn stroke(&mut self, map: &mut Map, hoovered_hex: Hex, erase: bool, just_pressed: bool) {
+        match self.shape() {
+            BrushShape::Area | BrushShape::Ring => {
+                let hexes = self.brush_hexes(&map.grid, hoovered_hex);
+                self.paint(map, hoovered_hex, hexes, erase);
+            }
+            BrushShape::Line | BrushShape::Cone => {
+                if just_pressed && self.shape_start.is_none() {
+                    self.shape_start = Some((hoovered_hex, erase));
+                }
+            }
+            BrushShape::Polygon => {
+                if !just_pressed {
+                    return;
+                }
+                // Clicking the first corner closes the polygon, right clicking elsewhere cancels it
+                if self.polygon.len() >= 3 && self.polygon[0] == hoovered_hex {
+                    let hexes = map.grid.polygon(&self.polygon);
+                    self.polygon.clear();
+                    self.paint(map, hoovered_hex, hexes, erase);
+                } else if erase {
+                    self.polygon.clear();
+                } else if self.polygon.last() != Some(&hoovered_hex) {
+                    self.polygon.push(hoovered_hex);
+                }
+            }
+        }
+    }
+
+    /// Sets a tile, and its copies around the symmetry center
+    fn paint_tile(&mut self, map: &mut Map, hex: Hex, tile: Option<Tile>) {
+        let center = self.symmetry_center;
+        match tile {
+            Some(tile) => {
+                for (hex, tile) in self.symmetry.tiles(&map.grid, center, hex, tile) {
+                    self.history.set(map, hex, Some(tile));
+                }
+            }
+            None => {
+                for hex in self.symmetry.cells(&map.grid, center, hex) {
+                    self.history.set(map, hex, None);
+                }
+            }
+        }
+    }
+
+    /// Paints or erases `hexes` and their symmetric copies with the current tool,
+    /// flattening to the height at `origin`
+    fn paint(&mut self, map: &mut Map, origin: Hex, hexes: Vec<Hex>, erase: bool) {
+        if self.tool == BrushTool::Tiles {
+            for hex in hexes {
+                self.paint_tile(map, hex, (!erase).then_some(self.brush));
+            }
+            return;
+        }
+        let hexes = Vec::from_iter(
+            hexes
+                .into_iter()
+                .flat_map(|hex| self.symmetry.cells(&map.grid, self.symmetry_center, hex)),
+        );
+        match self.tool {
+            BrushTool::Terrain => {
+                let terrain = if erase { Terrain::Normal } else { self.terrain };
+                for hex in hexes {
+                    map.set_terrain(hex, terrain);
+                }
+            }
+            BrushTool::Elevation if erase => {
+                for hex in hexes {
+                    map.set_elevation(hex, 0);
+                }
+            }
+            BrushTool::Elevation => {
+                let flatten_to = *self.flatten_to.get_or_insert(map.elevation_at(origin));
+                for hex in hexes {
+                    if !self.elevation_stroke.insert(hex) {
+                        continue;
+                    }
+                    let elevation = match self.elevation_mode {
+                        ElevationMode::Raise => map.elevation_at(hex) + 1,
+                        ElevationMode::Lower => map.elevation_at(hex) - 1,
+                        ElevationMode::Flatten => flatten_to,
+                    };
+                    map.set_elevation(hex, elevation);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    pub fn status(&self) -> String {
+        let status = match self.tool {
+            BrushTool::Tiles => format!(
+                "brush: {:?} {:?} size {}{}{}",
+                self.brush,
+                self.shape(),
+                self.brush_size,
+                if self.auto_walls { ", auto walls" } else { "" },
+                self.symmetry_status()
+            ),
+            BrushTool::Fill => format!(
+                "fill: {:?}{}{}",
+                self.brush,
+                if self.auto_walls { ", auto walls" } else { "" },
+                self.symmetry_status()
+            ),
+            BrushTool::Terrain => format!(
+                "terrain: {:?} {:?} size {}{}",
+                self.terrain,
+                self.shape(),
+                self.brush_size,
+                self.symmetry_status()
+            ),
+            BrushTool::Edges => format!("edge: {:?}", self.edge_kind),
+            BrushTool::Elevation => {
+                format!(
+                    "elevation: {:?} {:?} size {}{}",
+                    self.elevation_mode,
+                    self.shape(),
+                    self.brush_size,
+                    self.symmetry_status()
+                )
+            }
+            BrushTool::Background => "background: drag to move, wheel to scale, 1/2 to turn".into(),
+            BrushTool::Calibrate => format!(
+                "calibrate: span {}, click the {} cell center",
+                self.calibration_span,
+                if self.calibration_start.is_some() {
+                    "second"
+                } else {
+                    "first"
+                }
+            ),
+            BrushTool::Generate => format!(
+                "generate: {:?} seed {} size {}",
+                self.generator, self.seed, self.brush_size
+            ),
+            BrushTool::Select => format!(
+                "select: {:?} {} cells, clipboard {} tiles{}",
+                self.select_mode,
+                self.selection.cells.len(),
+                self.clipboard.tiles.len(),
+                if self.pasting { ", pasting" } else { "" }
+            ),
+            BrushTool::Stamp if self.stamps.is_empty() => {
+                format!("stamp: none saved in {STAMP_DIR} for this grid")
+            }
+            BrushTool::Stamp => format!(
+                "stamp: {}/{} {} tiles\n{}",
+                self.stamp + 1,
+                self.stamps.len(),
+                self.stamp_tiles.tiles.len(),
+                Vec::from_iter(self.stamps.iter().enumerate().map(|(i, stamp)| {
+                    if i == self.stamp {
+                        format!("[{}]", stamp.name)
+                    } else {
+                        stamp.name.clone()
+                    }
+                }))
+                .join(" ")
+            ),
+            BrushTool::Merge => format!(
+                "merge: {:?}, {}",
+                self.merge_policy,
+                match &self.merge_source {
+                    Some(source) => format!("{} at the cursor", source.current_map_file),
+                    None => "drop a map file to place".into(),
+                }
+            ),
+        };
+        match &self.lint {
+            Some(issues) => {
+                let errors = issues
+                    .iter()
+                    .filter(|issue| issue.kind.severity() == Severity::Error)
+                    .count();
+                format!(
+                    "{status}\nlint: {errors} errors, {} warnings",
+                    issues.len() - errors
+                )
+            }
+            None => status,
+        }
+    }
+
+    fn symmetry_status(&self) -> String {
+        let center = self.symmetry_center;
+        match self.symmetry {
+            Symmetry::Off => String::new(),
+            Symmetry::Mirror(axis) => format!(", mirror {axis} at {},{}", center.x, center.y),
+            Symmetry::Radial(folds) => format!(", radial {folds} at {},{}", center.x, center.y),
+        }
+    }
+
     pub fn draw(&self, map: &Map, theme: &Theme) {
         for (&hex, alpha) in self.to_fade.iter() {
-            let pos = h2q(map.hex_layout.hex_to_world_pos(hex));
-            self.brush.draw(
-                pos,
-                map.hex_size,
-                theme.color(ThemeColor::Light).with_alpha(0.5 * alpha),
-                theme.color(ThemeColor::Normal).with_alpha(0.5 * alpha),
-                &map.tile_variants,
+            let pos = map.grid.cell_to_world(hex);
+            match self.tool {
+                BrushTool::Tiles | BrushTool::Fill => self.brush.draw(
+                    pos,
+                    map.grid.size,
+                    theme.color(ThemeColor::Light).with_alpha(0.5 * alpha),
+                    theme.color(ThemeColor::Normal).with_alpha(0.5 * alpha),
+                    &map.tile_variants,
+                ),
+                BrushTool::Terrain => {
+                    let color = theme.terrain_color(self.terrain).with_alpha(0.5 * alpha);
+                    Tile::Full.draw(pos, map.grid.size, color, color, &map.tile_variants);
+                }
+                BrushTool::Generate => {
+                    let color = theme.color(ThemeColor::Normal).with_alpha(0.25 * alpha);
+                    Tile::Full.draw(pos, map.grid.size, color, color, &map.tile_variants);
+                }
+                BrushTool::Elevation => {
+                    let color = match self.elevation_mode {
+                        ElevationMode::Raise => theme.color(ThemeColor::Lighter),
+                        ElevationMode::Lower => theme.color(ThemeColor::Darker),
+                        ElevationMode::Flatten => theme.color(ThemeColor::Normal),
+                    }
+                    .with_alpha(0.5 * alpha);
+                    Tile::Full.draw(pos, map.grid.size, color, color, &map.tile_variants);
+                }
+                BrushTool::Edges
+                | BrushTool::Background
+                | BrushTool::Calibrate
+                | BrushTool::Select
+                | BrushTool::Stamp
+                | BrushTool::Merge => (),
+            }
+        }
+
+        if self.paints() {
+            self.symmetry.draw(
+                &map.grid,
+                self.symmetry_center,
+                theme.color(ThemeColor::Light).with_alpha(0.5),
             );
         }
-    }
 
-    pub fn insert_walls(&mut self, map: &mut Map) {
-        let mc = map.tiles.clone();
-        for (hex, tile) in mc.iter() {
-            if *tile != Tile::Empty {
-                continue;
+        if self.tool == BrushTool::Select {
+            let color = theme.color(ThemeColor::Light).with_alpha(0.25);
+            for hex in self.selection.cells.iter() {
+                let pos = map.grid.cell_to_world(*hex);
+                Tile::Full.draw(pos, map.grid.size, color, color, &map.tile_variants);
             }
-            for n0 in hex.all_neighbors() {
-                if map.tiles.contains_key(&n0) {
-                    continue;
+            self.selection
+                .draw_path(self.select_mode, theme.color(ThemeColor::Light));
+            if self.pasting {
+                for (hex, tile) in self.clipboard.placed(map.grid.world_to_cell(self.mouse)) {
+                    tile.draw(
+                        map.grid.cell_to_world(hex),
+                        map.grid.size,
+                        theme.color(ThemeColor::Light).with_alpha(0.5),
+                        theme.color(ThemeColor::Normal).with_alpha(0.5),
+                        &map.tile_variants,
+                    );
                 }
+            }
+        }
 
-                let mut ns = [false; 6];
-                for (v, n1) in n0.all_neighbors().into_iter().enumerate() {
-                    if let Some(tile) = map.tiles.get(&n1)
-                        && *tile == Tile::Empty
-                    {
-                        ns[v] = true;
-                    }
+        if self.tool == BrushTool::Stamp {
+            for (hex, tile) in self.stamp_tiles.placed(map.grid.world_to_cell(self.mouse)) {
+                tile.draw(
+                    map.grid.cell_to_world(hex),
+                    map.grid.size,
+                    theme.color(ThemeColor::Light).with_alpha(0.5),
+                    theme.color(ThemeColor::Normal).with_alpha(0.5),
+                    &map.tile_variants,
+                );
+            }
+        }
+
+        if self.tool == BrushTool::Merge
+            && let Some(source) = &self.merge_source
+        {
+            let offset = map.grid.world_to_cell(self.mouse);
+            let conflict_color = theme.color(ThemeColor::Lighter).with_alpha(0.5);
+            for merged in map.merged_tiles(source, offset, self.merge_policy) {
+                let pos = map.grid.cell_to_world(merged.hex);
+                merged.tile.draw(
+                    pos,
+                    map.grid.size,
+                    theme.color(ThemeColor::Light).with_alpha(0.5),
+                    theme.color(ThemeColor::Normal).with_alpha(0.5),
+                    &map.tile_variants,
+                );
+                if merged.conflict {
+                    let size = map.grid.size / 2.;
+                    draw_circle_lines(pos.x, pos.y, size, 2., conflict_color);
                 }
+            }
+        }
+
+        for issue in self.lint.iter().flatten() {
+            let color = match issue.kind.severity() {
+                Severity::Error => theme.color(ThemeColor::Lighter),
+                Severity::Warning => theme.color(ThemeColor::Normal),
+            }
+            .with_alpha(0.5);
+            for hex in issue.hexes.iter() {
+                let pos = map.grid.cell_to_world(*hex);
+                Tile::Full.draw(pos, map.grid.size, color, color, &map.tile_variants);
+            }
+        }
 
-                let tile = match ns {
-                    // 6
-                    [true, true, true, true, true, true] => Tile::Empty,
-
-                    // 5
-                    [true, true, true, true, true, false] => Tile::Empty,
-                    [true, true, true, true, false, true] => Tile::Empty,
-                    [true, true, true, false, true, true] => Tile::Empty,
-                    [true, true, false, true, true, true] => Tile::Empty,
-                    [true, false, true, true, true, true] => Tile::Empty,
-                    [false, true, true, true, true, true] => Tile::Empty,
-
-                    // 4
-                    [true, true, true, true, false, false] => Tile::Small { rotation: 3 },
-                    [false, true, true, true, true, false] => Tile::Small { rotation: 4 },
-                    [false, false, true, true, true, true] => Tile::Small { rotation: 5 },
-                    [true, false, false, true, true, true] => Tile::Small { rotation: 0 },
-                    [true, true, false, false, true, true] => Tile::Small { rotation: 1 },
-                    [true, true, true, false, false, true] => Tile::Small { rotation: 2 },
-
-                    // 3
-                    [true, true, true, false, false, false] => Tile::Half { rotation: 2 },
-                    [false, true, true, true, false, false] => Tile::Half { rotation: 3 },
-                    [false, false, true, true, true, false] => Tile::Half { rotation: 4 },
-                    [false, false, false, true, true, true] => Tile::Half { rotation: 5 },
-                    [true, false, false, false, true, true] => Tile::Half { rotation: 0 },
-                    [true, true, false, false, false, true] => Tile::Half { rotation: 1 },
-
-                    // 2
-                    [true, true, false, false, false, false] => Tile::Large { rotation: 5 },
-                    [false, true, true, false, false, false] => Tile::Large { rotation: 0 },
-                    [false, false, true, true, false, false] => Tile::Large { rotation: 1 },
-                    [false, false, false, true, true, false] => Tile::Large { rotation: 2 },
-                    [false, false, false, false, true, true] => Tile::Large { rotation: 3 },
-                    [true, false, false, false, false, true] => Tile::Large { rotation: 4 },
-
-                    // 1
-                    [true, false, false, false, false, false] => Tile::Full,
-                    [false, true, false, false, false, false] => Tile::Full,
-                    [false, false, true, false, false, false] => Tile::Full,
-                    [false, false, false, true, false, false] => Tile::Full,
-                    [false, false, false, false, true, false] => Tile::Full,
-                    [false, false, false, false, false, true] => Tile::Full,
-
-                    // 0
-                    [false, false, false, false, false, false] => Tile::Full,
-
-                    _ => continue,
-                };
-                map.tiles.insert(n0, tile);
+        if self.tool == BrushTool::Calibrate {
+            let color = theme.color(ThemeColor::Light).with_alpha(0.5);
+            let cells = match self.calibration_start {
+                Some(start) => {
+                    draw_line(start.x, start.y, self.mouse.x, self.mouse.y, 2., color);
+                    let (a, b) = self.calibration_cells(&map.grid, start, self.mouse);
+                    vec![a, b]
+                }
+                None => vec![map.grid.world_to_cell(self.mouse)],
+            };
+            for cell in cells {
+                let pos = map.grid.cell_to_world(cell);
+                Tile::Full.draw(pos, map.grid.size, color, color, &map.tile_variants);
+                draw_circle(pos.x, pos.y, 3., theme.color(ThemeColor::Darker));
             }
         }
+
+        if let Some(edge) = self.hoovered_edge {
+            let (from, to) = edge.endpoints(&map.grid);
+            EdgeFeature::new(self.edge_kind).draw(
+                from,
+                to,
+                theme.color(ThemeColor::Normal).with_alpha(0.5),
+            );
+        }
+    }
+
+    /// Forgets the strokes, after the map changed under them
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+    }
+
+    /// Walls every cell without a tile that touches the floor
+    pub fn insert_walls(&mut self, map: &mut Map) {
+        for (hex, tile) in map.wall_changes(map.active_layer, map.floor_border(map.active_layer)) {
+            self.history.set(map, hex, tile);
+        }
+    }
+
+    /// Re-walls the cells around where the floor changed since the last call,
+    /// leaving the walls painted during the stroke alone
+    fn follow_floor(&mut self, map: &mut Map) {
+        // Other layers hold decoration drawn over the floor, not floor
+        if map.active_layer != Map::FLOOR_LAYER {
+            return;
+        }
+        let stroke = self.history.stroke();
+        // Undoing mid-stroke closes the stroke early
+        if self.walled > stroke.len() {
+            self.walled = 0;
+        }
+        let is_floor = |tile: Option<Tile>| tile == Some(Tile::Empty);
+        let mut changed = HashSet::new();
+        for change in stroke.iter().skip(self.walled) {
+            if change.layer != map.active_layer {
+                continue;
+            }
+            if is_floor(change.before) != is_floor(change.after) {
+                changed.insert(change.hex);
+            }
+            // Erased cells stay empty, even with floor all around them
+            if !is_floor(change.after) {
+                self.stroke_walls.insert(change.hex);
+            }
+        }
+        let cells = Vec::from_iter(
+            changed
+                .iter()
+                .flat_map(|hex| [vec![*hex], map.grid.touching(*hex)].concat())
+                .filter(|hex| !self.stroke_walls.contains(hex)),
+        );
+        // Most frames change no floor
+        if !cells.is_empty() {
+            for (hex, tile) in map.wall_changes(map.active_layer, cells) {
+                self.history.set(map, hex, tile);
+            }
+        }
+        self.walled = self.history.stroke().len();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn erasing_floor_keeps_the_cell_empty() {
+        let mut map = Map::default();
+        map.build_tile_variants();
+        for hex in Hex::ZERO.range(3) {
+            map.active_tiles_mut().insert(hex, Tile::Empty);
+        }
+        let mut brush = Brush::default();
+        brush.history.set(&mut map, Hex::ZERO, None);
+        brush.follow_floor(&mut map);
+        assert_eq!(map.active_tiles().get(&Hex::ZERO), None);
+        // The floor around the hole needs no walls
+        assert_eq!(brush.history.stroke().len(), 1);
+    }
+
+    #[test]
+    fn painting_floor_walls_the_cells_around_it() {
+        let mut map = Map::default();
+        map.build_tile_variants();
+        let mut brush = Brush::default();
+        brush.history.set(&mut map, Hex::ZERO, Some(Tile::Empty));
+        brush.follow_floor(&mut map);
+        for hex in Hex::ZERO.all_neighbors() {
+            assert_eq!(map.active_tiles().get(&hex), Some(&Tile::Full));
+        }
+    }
+
+    fn walled_room() -> Map {
+        let mut map = Map::default();
+        map.build_tile_variants();
+        for hex in Hex::ZERO.range(2) {
+            map.active_tiles_mut().insert(hex, Tile::Empty);
+        }
+        for hex in Hex::ZERO.ring(3) {
+            map.active_tiles_mut().insert(hex, Tile::Full);
+        }
+        map
+    }
+
+    #[test]
+    fn fill_stops_at_walls() {
+        let map = walled_room();
+        let mut hexes = Brush::default().fill_hexes(&map, Hex::ZERO).unwrap();
+        hexes.sort_by_key(|hex| (hex.y, hex.x));
+        let mut room = Vec::from_iter(Hex::ZERO.range(2));
+        room.sort_by_key(|hex| (hex.y, hex.x));
+        assert_eq!(hexes, room);
+    }
+
+    #[test]
+    fn fill_leaking_past_the_limit_is_cancelled() {
+        let map = walled_room();
+        let mut brush = Brush::default();
+        assert_eq!(brush.fill_hexes(&map, Hex::new(10, 0)), None);
+        // Exactly as many cells as allowed still fill
+        brush.fill_max_size = 19;
+        assert_eq!(
+            brush.fill_hexes(&map, Hex::ZERO).map(|hexes| hexes.len()),
+            Some(19)
+        );
+        brush.fill_max_size = 18;
+        assert_eq!(brush.fill_hexes(&map, Hex::ZERO), None);
+    }
+
+    #[test]
+    fn fill_preview_follows_tile_changes() {
+        let mut map = walled_room();
+        let mut brush = Brush::default();
+        let preview_len = |brush: &Brush| {
+            brush
+                .fill_preview
+                .as_ref()
+                .and_then(|preview| preview.hexes.as_ref())
+                .map(|hexes| hexes.len())
+        };
+        brush.refresh_fill_preview(&map, Hex::ZERO);
+        assert_eq!(preview_len(&brush), Some(19));
+        map.active_tiles_mut().insert(Hex::ZERO, Tile::Full);
+        brush.refresh_fill_preview(&map, Hex::ZERO);
+        assert_eq!(preview_len(&brush), Some(1));
+        brush.refresh_fill_preview(&map, Hex::new(1, 0));
+        assert_eq!(preview_len(&brush), Some(18));
     }
 }
@@ -0,0 +1,135 @@
+use std::collections::{HashSet, VecDeque};
+
+use hexx::Hex;
+
+use crate::game::map::{Map, chunks::Chunks, tile::Tile};
+
+/// One tile write, with what was there before
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileChange {
+    pub layer: usize,
+    pub hex: Hex,
+    pub before: Option<Tile>,
+    pub after: Option<Tile>,
+}
+
+/// Undo and redo stacks of brush strokes, oldest strokes dropped past `max_strokes`
+pub struct History {
+    undo: VecDeque<Vec<TileChange>>,
+    redo: Vec<Vec<TileChange>>,
+    stroke: Vec<TileChange>,
+    max_strokes: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            stroke: Vec::new(),
+            max_strokes: 128,
+        }
+    }
+}
+
+impl History {
+    /// Writes a tile on the active layer as part of the current stroke
+    pub fn set(&mut self, map: &mut Map, hex: Hex, tile: Option<Tile>) {
+        let before = match tile {
+            Some(tile) => map.active_tiles_mut().insert(hex, tile),
+            None => map.active_tiles_mut().remove(&hex),
+        };
+        if before != tile {
+            self.stroke.push(TileChange {
+                layer: map.active_layer,
+                hex,
+                before,
+                after: tile,
+            });
+        }
+    }
+
+    /// Adds the tiles written on any layer since `before` was copied from the map to the
+    /// current stroke, for edits made to the map directly
+    pub fn record(&mut self, before: &[Chunks<Tile>], map: &Map) {
+        for (layer, tiles) in map.layers.iter().map(|layer| &layer.tiles).enumerate() {
+            let old = before.get(layer);
+            let hexes = HashSet::<Hex>::from_iter(
+                tiles
+                    .keys()
+                    .chain(old.into_iter().flat_map(|old| old.keys()))
+                    .copied(),
+            );
+            for hex in hexes {
+                let before = old.and_then(|old| old.get(&hex)).copied();
+                let after = tiles.get(&hex).copied();
+                if before != after {
+                    self.stroke.push(TileChange {
+                        layer,
+                        hex,
+                        before,
+                        after,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Changes of the stroke still in progress
+    pub fn stroke(&self) -> &[TileChange] {
+        &self.stroke
+    }
+
+    /// Closes the current stroke, if anything changed during it
+    pub fn end_stroke(&mut self) {
+        if self.stroke.is_empty() {
+            return;
+        }
+        self.undo.push_back(std::mem::take(&mut self.stroke));
+        if self.undo.len() > self.max_strokes {
+            self.undo.pop_front();
+        }
+        self.redo.clear();
+    }
+
+    pub fn undo(&mut self, map: &mut Map) {
+        self.end_stroke();
+        if let Some(stroke) = self.undo.pop_back() {
+            for change in stroke.iter().rev() {
+                apply(map, change.layer, change.hex, change.before);
+            }
+            self.redo.push(stroke);
+        }
+    }
+
+    pub fn redo(&mut self, map: &mut Map) {
+        self.end_stroke();
+        if let Some(stroke) = self.redo.pop() {
+            for change in stroke.iter() {
+                apply(map, change.layer, change.hex, change.after);
+            }
+            self.undo.push_back(stroke);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.stroke.clear();
+    }
+
+    /// Number of strokes that can be undone and redone
+    pub fn counts(&self) -> (usize, usize) {
+        (self.undo.len(), self.redo.len())
+    }
+}
+
+fn apply(map: &mut Map, layer: usize, hex: Hex, tile: Option<Tile>) {
+    let Some(layer) = map.layers.get_mut(layer) else {
+        return;
+    };
+    match tile {
+        Some(tile) => layer.tiles.insert(hex, tile),
+        None => layer.tiles.remove(&hex),
+    };
+}
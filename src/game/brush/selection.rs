@@ -0,0 +1,209 @@
+use std::collections::{HashSet, VecDeque};
+
+use hexx::Hex;
+use macroquad::prelude::*;
+
+use crate::game::map::{
+    grid::{Grid, polygon_contains},
+    tile::Tile,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SelectMode {
+    Box,
+    Lasso,
+    /// Connected cells holding the same tile as the clicked one
+    Wand,
+}
+
+impl SelectMode {
+    pub const ALL: [SelectMode; 3] = [SelectMode::Box, SelectMode::Lasso, SelectMode::Wand];
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Selection {
+    pub cells: HashSet<Hex>,
+    /// World positions of the mouse since the drag started
+    pub path: Vec<Vec2>,
+}
+
+impl Selection {
+    pub fn drag(&mut self, mouse: Vec2) {
+        if self.path.last() != Some(&mouse) {
+            self.path.push(mouse);
+        }
+    }
+
+    /// Selects the cells whose centers are inside the dragged box or lasso
+    pub fn finish(&mut self, grid: &Grid, mode: SelectMode) {
+        let path = std::mem::take(&mut self.path);
+        let (Some(first), Some(last)) = (path.first(), path.last()) else {
+            return;
+        };
+        let polygon = match mode {
+            SelectMode::Box => vec![*first, vec2(last.x, first.y), *last, vec2(first.x, last.y)],
+            SelectMode::Lasso => path,
+            SelectMode::Wand => return,
+        };
+        let min = polygon.iter().fold(Vec2::INFINITY, |a, b| a.min(*b));
+        let max = polygon.iter().fold(Vec2::NEG_INFINITY, |a, b| a.max(*b));
+        let (a, b) = (grid.world_to_cell(min), grid.world_to_cell(max));
+        let radius = grid.distance(a, b) + 1;
+        let center = grid.world_to_cell((min + max) / 2.);
+        self.cells = HashSet::from_iter(
+            grid.range(center, radius)
+                .into_iter()
+                .filter(|cell| polygon_contains(&polygon, grid.cell_to_world(*cell))),
+        );
+    }
+
+    /// Selects the cells connected to `start` holding the same tile
+    pub fn wand(&mut self, grid: &Grid, start: Hex, tile_at: impl Fn(Hex) -> Option<Tile>) {
+        self.cells.clear();
+        let Some(tile) = tile_at(start) else {
+            return;
+        };
+        self.cells.insert(start);
+        let mut queue = VecDeque::from([start]);
+        while let Some(cell) = queue.pop_front() {
+            for n in grid.neighbors(cell) {
+                if tile_at(n) == Some(tile) && self.cells.insert(n) {
+                    queue.push_back(n);
+                }
+            }
+        }
+    }
+
+    pub fn draw_path(&self, mode: SelectMode, color: Color) {
+        let (Some(first), Some(last)) = (self.path.first(), self.path.last()) else {
+            return;
+        };
+        match mode {
+            SelectMode::Box => {
+                let min = first.min(*last);
+                let size = (*last - *first).abs();
+                draw_rectangle_lines(min.x, min.y, size.x, size.y, 2., color);
+            }
+            SelectMode::Lasso => {
+                for (a, b) in self.path.iter().zip(self.path.iter().skip(1)) {
+                    draw_line(a.x, a.y, b.x, b.y, 2., color);
+                }
+            }
+            SelectMode::Wand => (),
+        }
+    }
+}
+
+/// Copied tiles, relative to the cell they are pasted at
+#[derive(Debug, Clone, Default)]
+pub struct Clipboard {
+    pub tiles: Vec<(Hex, Tile)>,
+}
+
+impl Clipboard {
+    /// Copies the selected tiles around the cell nearest to their middle
+    pub fn copy(grid: &Grid, cells: &HashSet<Hex>, tile_at: impl Fn(Hex) -> Option<Tile>) -> Self {
+        let mut tiles = Vec::from_iter(
+            cells
+                .iter()
+                .filter_map(|cell| tile_at(*cell).map(|tile| (*cell, tile))),
+        );
+        tiles.sort_by_key(|(hex, _)| (hex.y, hex.x));
+        if tiles.is_empty() {
+            return Self::default();
+        }
+        let middle = tiles
+            .iter()
+            .map(|(hex, _)| grid.cell_to_world(*hex))
+            .sum::<Vec2>()
+            / tiles.len() as f32;
+        let anchor = grid.world_to_cell(middle);
+        Self {
+            tiles: Vec::from_iter(tiles.into_iter().map(|(hex, tile)| (hex - anchor, tile))),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty()
+    }
+
+    pub fn rotate(&mut self, grid: &Grid, steps: i32) {
+        for (hex, tile) in self.tiles.iter_mut() {
+            *hex = grid.rotate_cell(*hex, Hex::ZERO, steps);
+            *tile = grid.rotate_tile(*tile, steps);
+        }
+    }
+
+    pub fn mirror(&mut self, grid: &Grid) {
+        for (hex, tile) in self.tiles.iter_mut() {
+            *hex = grid.mirror_cell(*hex, Hex::ZERO);
+            *tile = grid.mirror_tile(*tile);
+        }
+    }
+
+    /// Cells and tiles when pasted at `at`
+    pub fn placed(&self, at: Hex) -> impl Iterator<Item = (Hex, Tile)> + '_ {
+        self.tiles.iter().map(move |(hex, tile)| (at + *hex, *tile))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::map::grid::GridKind;
+
+    /// Sorted like `sorted` sorts
+    const TILES: [(Hex, Tile); 4] = [
+        (Hex::new(2, -1), Tile::Large { rotation: 2 }),
+        (Hex::new(0, 0), Tile::Empty),
+        (Hex::new(1, 0), Tile::Half { rotation: 1 }),
+        (Hex::new(1, 1), Tile::Small { rotation: 0 }),
+    ];
+
+    fn copied(grid: &Grid) -> Clipboard {
+        let cells = HashSet::from_iter(TILES.iter().map(|(hex, _)| *hex));
+        let tile_at = |hex: Hex| TILES.iter().find(|(h, _)| *h == hex).map(|(_, t)| *t);
+        Clipboard::copy(grid, &cells, tile_at)
+    }
+
+    fn sorted(clipboard: &Clipboard) -> Vec<(Hex, Tile)> {
+        let mut tiles = clipboard.tiles.clone();
+        tiles.sort_by_key(|(hex, _)| (hex.y, hex.x));
+        tiles
+    }
+
+    #[test]
+    fn copies_paste_back_where_they_came_from() {
+        let grid = Grid::default();
+        let clipboard = copied(&grid);
+        assert_eq!(clipboard.tiles.len(), 4);
+        // The anchor is the cell in the middle of the copied ones
+        let anchor = Hex::new(1, 0);
+        let mut placed = Vec::from_iter(clipboard.placed(anchor));
+        placed.sort_by_key(|(hex, _)| (hex.y, hex.x));
+        assert_eq!(placed, TILES);
+    }
+
+    #[test]
+    fn turns_and_mirrors_come_back_around() {
+        for kind in [GridKind::PointyHex, GridKind::FlatHex, GridKind::Square] {
+            let grid = Grid::new(kind, 1.);
+            let original = sorted(&copied(&grid));
+
+            let mut clipboard = copied(&grid);
+            clipboard.rotate(&grid, 1);
+            assert_ne!(sorted(&clipboard), original, "{kind:?}");
+            clipboard.rotate(&grid, -1);
+            assert_eq!(sorted(&clipboard), original, "{kind:?}");
+            for _ in 0..grid.sides() {
+                clipboard.rotate(&grid, 1);
+            }
+            assert_eq!(sorted(&clipboard), original, "{kind:?}");
+
+            clipboard.mirror(&grid);
+            assert_ne!(sorted(&clipboard), original, "{kind:?}");
+            clipboard.mirror(&grid);
+            assert_eq!(sorted(&clipboard), original, "{kind:?}");
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use std::f32::consts::{FRAC_PI_2, PI};
+
+use hexx::Hex;
+use macroquad::prelude::*;
+
+use crate::game::map::{grid::Grid, tile::Tile, transform::MapTransform};
+
+/// How brush edits are repeated around a center cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Symmetry {
+    Off,
+    /// Across the axis through the center, turned `axis` half sides from vertical
+    Mirror(u8),
+    /// Repeated `folds` times around the center
+    Radial(u8),
+}
+
+impl Symmetry {
+    /// Off, then every mirror axis, then every fold count the grid allows
+    pub fn next(&self, grid: &Grid) -> Self {
+        let sides = grid.sides() as u8;
+        let folds_after = |folds: u8| (folds + 1..=sides).find(|n| sides.is_multiple_of(*n));
+        match *self {
+            Symmetry::Off => Symmetry::Mirror(0),
+            Symmetry::Mirror(axis) if axis + 1 < sides => Symmetry::Mirror(axis + 1),
+            Symmetry::Mirror(_) => folds_after(1).map_or(Symmetry::Off, Symmetry::Radial),
+            Symmetry::Radial(folds) => folds_after(folds).map_or(Symmetry::Off, Symmetry::Radial),
+        }
+    }
+
+    /// The moves making each copy of an edit around `center`, the edit itself first
+    fn transforms(&self, grid: &Grid, center: Hex) -> Vec<MapTransform> {
+        let sides = grid.sides() as u8;
+        let itself = MapTransform::Translate(Hex::ZERO);
+        match *self {
+            Symmetry::Mirror(axis) => vec![
+                itself,
+                MapTransform::Mirror {
+                    pivot: center,
+                    axis,
+                },
+            ],
+            Symmetry::Radial(folds) if folds > 0 && sides.is_multiple_of(folds) => {
+                let step = (sides / folds) as i32;
+                Vec::from_iter((0..folds as i32).map(|i| MapTransform::Rotate {
+                    pivot: center,
+                    steps: i * step,
+                }))
+            }
+            _ => vec![itself],
+        }
+    }
+
+    /// The cells an edit of `cell` is repeated at, `cell` first
+    pub fn cells(&self, grid: &Grid, center: Hex, cell: Hex) -> Vec<Hex> {
+        Vec::from_iter(
+            self.transforms(grid, center)
+                .into_iter()
+                .map(|transform| transform.cell(grid, cell)),
+        )
+    }
+
+    /// The cells and turned or mirrored tiles painting `tile` at `cell` is repeated with
+    pub fn tiles(&self, grid: &Grid, center: Hex, cell: Hex, tile: Tile) -> Vec<(Hex, Tile)> {
+        Vec::from_iter(
+            self.transforms(grid, center)
+                .into_iter()
+                .map(|transform| (transform.cell(grid, cell), transform.tile(grid, tile))),
+        )
+    }
+
+    /// Marks the center, and the mirror axis through it
+    pub fn draw(&self, grid: &Grid, center: Hex, color: Color) {
+        let pos = grid.cell_to_world(center);
+        if let Symmetry::Mirror(axis) = *self {
+            let angle = FRAC_PI_2 + axis as f32 * PI / grid.sides() as f32;
+            let reach = Vec2::from_angle(angle) * grid.size * 64.;
+            let (a, b) = (pos - reach, pos + reach);
+            draw_line(a.x, a.y, b.x, b.y, 2., color);
+        }
+        if *self != Symmetry::Off {
+            draw_circle_lines(pos.x, pos.y, grid.size / 2., 2., color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::map::grid::GridKind;
+
+    #[test]
+    fn fold_counts_not_dividing_the_sides_are_rejected() {
+        let grid = Grid::default();
+        let cell = Hex::new(2, 0);
+        assert_eq!(Symmetry::Radial(4).cells(&grid, Hex::ZERO, cell), [cell]);
+        assert_eq!(Symmetry::Radial(0).cells(&grid, Hex::ZERO, cell), [cell]);
+        let square = Grid::new(GridKind::Square, 1.);
+        assert_eq!(Symmetry::Radial(3).cells(&square, Hex::ZERO, cell), [cell]);
+
+        let cells = Symmetry::Radial(3).cells(&grid, Hex::ZERO, cell);
+        assert_eq!(cells.len(), 3);
+        assert_eq!(cells[0], cell);
+        assert!(cells.iter().all(|c| c.length() == 2));
+    }
+
+    #[test]
+    fn cycling_offers_only_fold_counts_dividing_the_sides() {
+        for (kind, folds) in [
+            (GridKind::PointyHex, vec![2, 3, 6]),
+            (GridKind::Square, vec![2, 4]),
+        ] {
+            let grid = Grid::new(kind, 1.);
+            let mut offered = Vec::new();
+            let mut symmetry = Symmetry::Off.next(&grid);
+            while symmetry != Symmetry::Off {
+                if let Symmetry::Radial(n) = symmetry {
+                    offered.push(n);
+                }
+                symmetry = symmetry.next(&grid);
+            }
+            assert_eq!(offered, folds, "{kind:?}");
+        }
+    }
+}
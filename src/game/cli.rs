@@ -0,0 +1,309 @@
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result, bail};
+use hexx::Hex;
+
+use crate::game::{
+    map::{
+        Map,
+        lint::Severity,
+        merge::MergePolicy,
+        svg::SvgOptions,
+        tiled::{TiledMap, TiledMapping},
+        transform::MapTransform,
+    },
+    theme::Theme,
+};
+
+/// Commands run without opening a window
+#[derive(Debug)]
+pub enum Command {
+    ExportSvg {
+        map: String,
+        output: String,
+        options: SvgOptions,
+    },
+    ImportTiled {
+        map: String,
+        mapping: String,
+        output: String,
+    },
+    Transform {
+        map: String,
+        output: String,
+        steps: Vec<TransformStep>,
+    },
+    Lint {
+        map: String,
+    },
+    Merge {
+        map: String,
+        other: String,
+        output: String,
+        offset: Hex,
+        policy: MergePolicy,
+    },
+    Diff {
+        base: String,
+        other: String,
+    },
+    /// Git merge driver: `myvtt merge3 %O %A %B %A`
+    Merge3 {
+        base: String,
+        ours: String,
+        theirs: String,
+        output: String,
+    },
+}
+
+/// One `transform` flag, applied in the order given
+#[derive(Debug)]
+pub enum TransformStep {
+    Apply(MapTransform),
+    Normalize,
+}
+
+impl Command {
+    pub const USAGE: &str = "usage: myvtt [map]
+       myvtt export-svg <map> <output.svg> [--grid] [--labels] [--gm]
+       myvtt import-tiled <map.tmx|map.tmj> <mapping.ron> <output.ron>
+       myvtt transform <map> <output.ron> [--pivot=x,y] [--translate=x,y] [--rotate=sides]
+                       [--mirror=axis] [--normalize]
+       myvtt merge <map> <other> <output.ron> [--offset=x,y] [--policy=ours|theirs|union]
+       myvtt lint <map>
+       myvtt diff <base> <other>
+       myvtt merge3 <base> <ours> <theirs> <output.ron>";
+
+    /// `None` when the arguments are meant for the app itself
+    pub fn parse(args: &[String]) -> Result<Option<Self>> {
+        let Some(command) = args.first() else {
+            return Ok(None);
+        };
+        let flags = Vec::from_iter(args.iter().filter(|arg| arg.starts_with("--")));
+        let positional = Vec::from_iter(args.iter().skip(1).filter(|arg| !arg.starts_with("--")));
+        match command.as_str() {
+            "export-svg" => {
+                let [map, output] = positional[..] else {
+                    bail!("{}", Self::USAGE);
+                };
+                let mut options = SvgOptions::default();
+                for flag in flags {
+                    match flag.as_str() {
+                        "--grid" => options.grid_lines = true,
+                        "--labels" => options.labels = true,
+                        "--gm" => options.gm = true,
+                        _ => bail!("unknown flag {flag}\n{}", Self::USAGE),
+                    }
+                }
+                Ok(Some(Self::ExportSvg {
+                    map: map.clone(),
+                    output: output.clone(),
+                    options,
+                }))
+            }
+            "import-tiled" => {
+                let [map, mapping, output] = positional[..] else {
+                    bail!("{}", Self::USAGE);
+                };
+                Ok(Some(Self::ImportTiled {
+                    map: map.clone(),
+                    mapping: mapping.clone(),
+                    output: output.clone(),
+                }))
+            }
+            "transform" => {
+                let [map, output] = positional[..] else {
+                    bail!("{}", Self::USAGE);
+                };
+                let mut pivot = Hex::ZERO;
+                let mut steps = Vec::new();
+                for flag in flags {
+                    let (name, value) = flag.split_once('=').unwrap_or((flag, ""));
+                    let step = match name {
+                        "--pivot" => {
+                            pivot = parse_hex(value)?;
+                            continue;
+                        }
+                        "--translate" => MapTransform::Translate(parse_hex(value)?),
+                        "--rotate" => MapTransform::Rotate {
+                            pivot,
+                            steps: value.parse().context("--rotate takes a number of sides")?,
+                        },
+                        "--mirror" => MapTransform::Mirror {
+                            pivot,
+                            axis: value.parse().context("--mirror takes an axis number")?,
+                        },
+                        "--normalize" => {
+                            steps.push(TransformStep::Normalize);
+                            continue;
+                        }
+                        _ => bail!("unknown flag {flag}\n{}", Self::USAGE),
+                    };
+                    steps.push(TransformStep::Apply(step));
+                }
+                Ok(Some(Self::Transform {
+                    map: map.clone(),
+                    output: output.clone(),
+                    steps,
+                }))
+            }
+            "lint" => {
+                let [map] = positional[..] else {
+                    bail!("{}", Self::USAGE);
+                };
+                Ok(Some(Self::Lint { map: map.clone() }))
+            }
+            "merge" => {
+                let [map, other, output] = positional[..] else {
+                    bail!("{}", Self::USAGE);
+                };
+                let mut offset = Hex::ZERO;
+                let mut policy = MergePolicy::KeepOurs;
+                for flag in flags {
+                    match flag.split_once('=') {
+                        Some(("--offset", value)) => offset = parse_hex(value)?,
+                        Some(("--policy", "ours")) => policy = MergePolicy::KeepOurs,
+                        Some(("--policy", "theirs")) => policy = MergePolicy::TakeTheirs,
+                        Some(("--policy", "union")) => policy = MergePolicy::UnionWalls,
+                        _ => bail!("unknown flag {flag}\n{}", Self::USAGE),
+                    }
+                }
+                Ok(Some(Self::Merge {
+                    map: map.clone(),
+                    other: other.clone(),
+                    output: output.clone(),
+                    offset,
+                    policy,
+                }))
+            }
+            "diff" => {
+                let [base, other] = positional[..] else {
+                    bail!("{}", Self::USAGE);
+                };
+                Ok(Some(Self::Diff {
+                    base: base.clone(),
+                    other: other.clone(),
+                }))
+            }
+            "merge3" => {
+                let [base, ours, theirs, output] = positional[..] else {
+                    bail!("{}", Self::USAGE);
+                };
+                Ok(Some(Self::Merge3 {
+                    base: base.clone(),
+                    ours: ours.clone(),
+                    theirs: theirs.clone(),
+                    output: output.clone(),
+                }))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    pub fn run(self) -> Result<()> {
+        match self {
+            Command::ExportSvg {
+                map,
+                output,
+                options,
+            } => {
+                let map = Map::from_file(&map)?;
+                map.export_svg(&output, &Theme::default(), &options)
+            }
+            Command::ImportTiled {
+                map: path,
+                mapping,
+                output,
+            } => {
+                let tiled = TiledMap::parse(&fs::read_to_string(&path)?)?;
+                let mapping = TiledMapping::from_ron(&fs::read_to_string(mapping)?)?;
+                let mut map = Map::default();
+                map.build_tile_variants();
+                let unmapped = map.import_tiled(&tiled, &mapping);
+                if !unmapped.is_empty() {
+                    eprintln!("warning: tile ids without a mapping were skipped: {unmapped:?}");
+                }
+                map.name = Path::new(&path)
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                fs::write(output, map.to_document().to_ron()?)?;
+                Ok(())
+            }
+            Command::Transform { map, output, steps } => {
+                let mut map = Map::from_file(&map)?;
+                for step in steps {
+                    let transform = match step {
+                        TransformStep::Apply(transform) => transform,
+                        TransformStep::Normalize => map.normalizing(),
+                    };
+                    map.transform(transform)?;
+                }
+                fs::write(output, map.to_document().to_ron()?)?;
+                Ok(())
+            }
+            Command::Lint { map } => {
+                let issues = Map::from_file(&map)?.lint();
+                for issue in issues.iter() {
+                    println!("{issue}");
+                }
+                let errors = issues
+                    .iter()
+                    .filter(|issue| issue.kind.severity() == Severity::Error)
+                    .count();
+                if errors > 0 {
+                    bail!("{map}: {errors} errors, {} warnings", issues.len() - errors);
+                }
+                Ok(())
+            }
+            Command::Merge {
+                map,
+                other,
+                output,
+                offset,
+                policy,
+            } => {
+                let mut map = Map::from_file(&map)?;
+                let conflicts = map.merge(&Map::from_file(&other)?, offset, policy)?;
+                if conflicts > 0 {
+                    eprintln!("{conflicts} cells used by both maps were resolved as {policy:?}");
+                }
+                fs::write(output, map.to_document().to_ron()?)?;
+                Ok(())
+            }
+            Command::Diff { base, other } => {
+                for change in Map::from_file(&base)?.diff(&Map::from_file(&other)?) {
+                    println!("{change}");
+                }
+                Ok(())
+            }
+            Command::Merge3 {
+                base,
+                ours,
+                theirs,
+                output,
+            } => {
+                let mut map = Map::from_file(&ours)?;
+                let conflicts =
+                    map.merge_three_way(&Map::from_file(&base)?, &Map::from_file(&theirs)?)?;
+                fs::write(&output, map.to_document().to_ron()?)?;
+                for conflict in conflicts.iter() {
+                    eprintln!("{conflict}");
+                }
+                // Written either way, the conflicts kept ours and are left to review
+                if !conflicts.is_empty() {
+                    bail!("{output}: {} conflicts resolved as ours", conflicts.len());
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// `x,y` cell coordinates
+fn parse_hex(value: &str) -> Result<Hex> {
+    let (x, y) = value
+        .split_once(',')
+        .with_context(|| format!("expected x,y cell coordinates, got {value:?}"))?;
+    Ok(Hex::new(x.trim().parse()?, y.trim().parse()?))
+}
@@ -4,13 +4,13 @@ pub mod entity;
 use std::{collections::HashMap, f32};
 
 use anyhow::Result;
-use hexx::{Hex, HexLayout};
+use hexx::Hex;
 use macroquad::prelude::*;
 
 use crate::game::{
     entities::{default::EntityEvent, entity::Entity},
     events::Events,
-    map::q2h,
+    map::{grid::Grid, transform::MapTransform},
     theme::Theme,
 };
 
@@ -57,12 +57,7 @@ impl Entities {
         Ok(())
     }
 
-    pub fn handle_events(
-        &mut self,
-        hex_layout: &HexLayout,
-        camera: &Camera2D,
-        _dt: f32,
-    ) -> Result<()> {
+    pub fn handle_events(&mut self, grid: &Grid, camera: &Camera2D, _dt: f32) -> Result<()> {
         self.entity_events.update();
 
         let drag = self.entity_events.pop(&EntityEvent::Drag);
@@ -71,8 +66,7 @@ impl Entities {
         let remove = self.entity_events.pop(&EntityEvent::Remove);
 
         if drag || drop || duplicate_drag || remove {
-            let hex =
-                hex_layout.world_pos_to_hex(q2h(camera.screen_to_world(mouse_position().into())));
+            let hex = grid.world_to_cell(camera.screen_to_world(mouse_position().into()));
 
             if drag && let Some((eid, entity)) = self.get_mut_entity_by_hex(hex) {
                 entity.to_alpha = 0.5;
@@ -140,11 +134,11 @@ impl Entities {
         Ok(())
     }
 
-    pub fn draw(&self, theme: &Theme, hex_layout: &HexLayout, camera: &Camera2D) {
-        let hex_inradius_size = hex_layout.scale.x * Self::INRADIUS_2;
+    pub fn draw(&self, theme: &Theme, grid: &Grid, camera: &Camera2D) {
+        let hex_inradius_size = grid.inradius() * 2.;
         for entity in self.entities.iter() {
             entity.draw(
-                hex_layout,
+                grid,
                 hex_inradius_size,
                 &self.textures,
                 self.entity_esp,
@@ -166,6 +160,13 @@ impl Entities {
         }
     }
 
+    /// Moves the entities along with the map
+    pub fn transform(&mut self, grid: &Grid, transform: MapTransform) {
+        for entity in self.entities.iter_mut() {
+            entity.hex = transform.cell(grid, entity.hex);
+        }
+    }
+
     pub fn get_mut_entity_by_hex(&mut self, hex: Hex) -> Option<(usize, &mut Entity)> {
         self.entities
             .iter_mut()
@@ -192,7 +193,4 @@ impl Entities {
             None
         }
     }
-
-    pub const INRADIUS: f32 = 0.866_025_4; // sqrt(3)/2
-    pub const INRADIUS_2: f32 = 1.732_050_8; // sqrt(3)/2 * 2
 }
@@ -1,10 +1,10 @@
 use std::collections::HashMap;
 
-use hexx::{Hex, HexLayout};
+use hexx::Hex;
 use macroquad::prelude::*;
 
 use crate::game::{
-    map::h2q,
+    map::grid::Grid,
     theme::{Theme, ThemeColor},
 };
 
@@ -89,13 +89,13 @@ impl Entity {
 
     pub fn draw(
         &self,
-        hex_layout: &HexLayout,
+        grid: &Grid,
         hex_inradius_size: f32,
         textures: &HashMap<String, Texture2D>,
         esp: bool,
         theme: &Theme,
     ) {
-        let pos = h2q(hex_layout.hex_to_world_pos(self.hex));
+        let pos = grid.cell_to_world(self.hex);
         self.draw_to(pos, hex_inradius_size, textures, esp, theme);
     }
 
@@ -14,12 +14,21 @@ pub enum Mode {
 pub struct GameState {
     pub mode: Mode,
     pub fullscreen: bool,
+    pub gm: bool,
     pub game_events: Events<GameEvent>,
 }
 
 #[derive(Clone, PartialEq, Eq, Hash)]
 pub enum GameEvent {
     ToggleFullScreen,
+    TogglePlayerView,
+    ExportSvg,
+    /// Turn the map by sides around the hovered cell
+    RotateMap(i8),
+    MirrorMap,
+    /// Move the hovered cell to the origin
+    MapToOrigin,
+    NormalizeMap,
     SwitchTo(Mode),
 }
 
@@ -32,6 +41,7 @@ impl Default for GameState {
         Self {
             mode: Mode::Normal,
             fullscreen: false,
+            gm: true,
             game_events: Events::from([
                 (
                     GameEvent::ToggleFullScreen,
@@ -40,6 +50,43 @@ impl Default for GameState {
                         vec![(EventS::JustPressed, EventT::Keyboard(KeyCode::F11))],
                     ],
                 ),
+                (
+                    GameEvent::TogglePlayerView,
+                    vec![vec![(EventS::JustPressed, EventT::Keyboard(KeyCode::P))]],
+                ),
+                (
+                    GameEvent::ExportSvg,
+                    vec![vec![(EventS::JustPressed, EventT::Keyboard(KeyCode::X))]],
+                ),
+                (
+                    GameEvent::RotateMap(-1),
+                    vec![vec![(
+                        EventS::JustPressed,
+                        EventT::Keyboard(KeyCode::Comma),
+                    )]],
+                ),
+                (
+                    GameEvent::RotateMap(1),
+                    vec![vec![(
+                        EventS::JustPressed,
+                        EventT::Keyboard(KeyCode::Period),
+                    )]],
+                ),
+                (
+                    GameEvent::MirrorMap,
+                    vec![vec![(
+                        EventS::JustPressed,
+                        EventT::Keyboard(KeyCode::Slash),
+                    )]],
+                ),
+                (
+                    GameEvent::MapToOrigin,
+                    vec![vec![(EventS::JustPressed, EventT::Keyboard(KeyCode::End))]],
+                ),
+                (
+                    GameEvent::NormalizeMap,
+                    vec![vec![(EventS::JustPressed, EventT::Keyboard(KeyCode::Home))]],
+                ),
                 (
                     GameEvent::SwitchTo(Mode::Normal),
                     vec![
@@ -65,6 +112,10 @@ impl GameState {
             set_fullscreen(self.fullscreen);
         }
 
+        if self.game_events.pop(&GameEvent::TogglePlayerView) {
+            self.gm = !self.gm;
+        }
+
         if self.game_events.pop(&GameEvent::SwitchTo(Mode::Normal)) {
             self.mode = Mode::Normal;
         }
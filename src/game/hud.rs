@@ -1,7 +1,7 @@
 use anyhow::Result;
 use macroquad::prelude::*;
 
-use crate::game::{camera_controller::CameraController, theme::Theme};
+use crate::game::{brush::Brush, camera_controller::CameraController, map::Map, theme::Theme};
 
 #[derive(Debug)]
 pub struct Hud {}
@@ -39,4 +39,26 @@ impl Hud {
 
         draw_fps();
     }
+
+    pub fn draw_map_editor(&mut self, _theme: &Theme, map: &Map, brush: &Brush) {
+        let layer = map.active_layer();
+        draw_multiline_text(
+            &format!(
+                "layer: {}/{} {}{}{} {:.2}\n{}\ngrid: {:?}\n",
+                map.active_layer + 1,
+                map.layers.len(),
+                layer.name,
+                if layer.visible { "" } else { " (hidden)" },
+                if layer.gm_only { " (gm)" } else { "" },
+                layer.opacity,
+                brush.status(),
+                map.grid.kind,
+            ),
+            12.,
+            74.,
+            32.,
+            None,
+            WHITE,
+        );
+    }
 }
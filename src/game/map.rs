@@ -1,108 +1,473 @@
 #![allow(dead_code)]
 
+pub mod background;
+pub mod chunks;
+pub mod diff;
+pub mod document;
+pub mod edge;
+pub mod generator;
+pub mod grid;
+pub mod layer;
+pub mod light;
+pub mod lint;
 mod load_save;
+pub mod merge;
+pub mod mesh;
+pub mod stamp;
+pub mod svg;
+pub mod terrain;
 pub mod tile;
+pub mod tiled;
+pub mod transform;
+pub mod uvtt;
+pub mod walls;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use anyhow::Result;
-use hexx::{Hex, HexLayout};
+use hexx::Hex;
 use macroquad::prelude::*;
 
 use crate::game::{
-    map::tile::{Tile, VertexType},
+    events::{Event, EventS, EventT, Events},
+    map::{
+        background::Background,
+        chunks::{ChunkCoord, Chunks, Viewport},
+        edge::{Edge, EdgeFeature, EdgeKind},
+        generator::Generation,
+        grid::{Grid, GridKind},
+        layer::Layer,
+        light::LightSource,
+        mesh::ChunkMeshes,
+        terrain::Terrain,
+        tile::Tile,
+        walls::WallTiles,
+    },
     theme::{Theme, ThemeColor},
 };
 
 pub struct Map {
-    pub hex_layout: HexLayout,
-    pub hex_size: f32,
-    pub tile_variants: HashMap<Tile, (Vec<Vec2>, Vec<Vec2>)>,
-    pub tiles: HashMap<Hex, Tile>,
+    pub grid: Grid,
+    pub tile_variants: HashMap<Tile, (Vec<Vec2>, Vec<Vec<Vec2>>)>,
+    /// Built with the tile variants, as it only changes with the grid
+    pub wall_tiles: WallTiles,
+    pub layers: Vec<Layer>,
+    pub active_layer: usize,
+    pub terrain: Chunks<Terrain>,
+    pub terrain_meshes: ChunkMeshes,
+    /// Height of each hex in levels, one level being as tall as a hex is wide
+    pub elevation: Chunks<i32>,
+    pub elevation_meshes: ChunkMeshes,
+    pub edges: HashMap<Edge, EdgeFeature>,
+    pub lights: Vec<LightSource>,
+    pub background: Option<Background>,
     pub current_map_file: String,
+    pub name: String,
+    pub author: String,
+    pub grid_scale: f32,
+    pub generations: Vec<Generation>,
 
     pub mouse_target: Vec2,
     pub smoothing_factor: f32,
+
+    map_events: Events<MapEvent>,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub enum MapEvent {
+    ToggleEdgeOpen,
+    ToggleEdgeLock,
 }
 
+impl Event for MapEvent {}
+
 impl Default for Map {
     fn default() -> Self {
-        let hex_size = 32.;
         Self {
-            hex_layout: HexLayout {
-                orientation: hexx::HexOrientation::Pointy,
-                origin: hexx::Vec2::ZERO,
-                scale: hexx::Vec2::new(hex_size, hex_size),
-            },
-            hex_size,
+            grid: Grid::default(),
             tile_variants: HashMap::new(),
-            tiles: HashMap::new(),
-            current_map_file: "assets/map/001".to_string(),
+            wall_tiles: WallTiles::default(),
+            layers: Self::default_layers(),
+            active_layer: 0,
+            terrain: Chunks::new(),
+            terrain_meshes: ChunkMeshes::default(),
+            elevation: Chunks::new(),
+            elevation_meshes: ChunkMeshes::default(),
+            edges: HashMap::new(),
+            lights: Vec::new(),
+            background: None,
+            current_map_file: "assets/map/001.ron".to_string(),
+            name: String::new(),
+            author: String::new(),
+            grid_scale: 5.,
+            generations: Vec::new(),
 
-            mouse_target: Vec2::new(screen_width() / 2., screen_height() / 2.),
+            mouse_target: Vec2::ZERO,
             smoothing_factor: 28.,
+
+            map_events: Events::from([
+                (
+                    MapEvent::ToggleEdgeOpen,
+                    vec![vec![(EventS::JustPressed, EventT::Keyboard(KeyCode::O))]],
+                ),
+                (
+                    MapEvent::ToggleEdgeLock,
+                    vec![vec![(EventS::JustPressed, EventT::Keyboard(KeyCode::L))]],
+                ),
+            ]),
         }
     }
 }
 
 impl Map {
+    /// Alpha of the elevation overlay per level
+    pub const ELEVATION_SHADE: f32 = 0.125;
+
     pub async fn load_tile_variants(&mut self) -> Result<()> {
-        self.tile_variants.insert(
-            Tile::Empty,
-            Tile::split_to_tile_variant([VertexType::Empty; 6]),
+        self.build_tile_variants();
+        Ok(())
+    }
+
+    pub fn build_tile_variants(&mut self) {
+        let vertecies = self.grid.vertecies();
+        self.tile_variants.clear();
+        for tile in self.grid.all_tiles() {
+            self.tile_variants.insert(
+                tile,
+                Tile::split_to_tile_variant(&self.grid.tile_split(tile), &vertecies),
+            );
+        }
+        self.wall_tiles = WallTiles::new(&self.grid);
+    }
+
+    pub fn set_grid(&mut self, kind: GridKind, size: f32) {
+        self.grid.kind = kind;
+        self.grid.size = size;
+        self.build_tile_variants();
+        self.invalidate_meshes();
+    }
+
+    /// Scales and moves the grid so the centers of the two cells land on the two
+    /// world positions, turning the background to make up for any rotation between them
+    pub fn calibrate_grid(&mut self, (hex_a, pos_a): (Hex, Vec2), (hex_b, pos_b): (Hex, Vec2)) {
+        let unit = Grid::new(self.grid.kind, 1.);
+        let unit_a = unit.cell_to_world(hex_a);
+        let unit_delta = unit.cell_to_world(hex_b) - unit_a;
+        let delta = pos_b - pos_a;
+        if unit_delta.length() == 0. || delta.length() == 0. {
+            return;
+        }
+        let size = delta.length() / unit_delta.length();
+        if let Some(background) = &mut self.background {
+            let angle = unit_delta.perp_dot(delta).atan2(unit_delta.dot(delta));
+            background.rotate_around(pos_a, -angle);
+        }
+        self.grid.origin = pos_a - unit_a * size;
+        self.set_grid(self.grid.kind, size);
+    }
+
+    /// The layer holding the floor and the walls fitted around it
+    pub const FLOOR_LAYER: usize = 0;
+
+    pub fn default_layers() -> Vec<Layer> {
+        vec![
+            Layer::new("floor"),
+            Layer::new("walls"),
+            Layer::new("decoration"),
+            Layer::new_gm_only("gm"),
+        ]
+    }
+
+    pub fn active_tiles(&self) -> &Chunks<Tile> {
+        &self.layers[self.active_layer].tiles
+    }
+
+    pub fn active_tiles_mut(&mut self) -> &mut Chunks<Tile> {
+        &mut self.layers[self.active_layer].tiles
+    }
+
+    pub fn active_layer(&self) -> &Layer {
+        &self.layers[self.active_layer]
+    }
+
+    pub fn active_layer_mut(&mut self) -> &mut Layer {
+        &mut self.layers[self.active_layer]
+    }
+
+    pub fn select_layer(&mut self, delta: isize) {
+        if self.layers.is_empty() {
+            return;
+        }
+        self.active_layer =
+            (self.active_layer as isize + delta).rem_euclid(self.layers.len() as isize) as usize;
+    }
+
+    pub fn draw(&mut self, theme: &Theme, camera: &Camera2D, gm: bool) {
+        for layer in self.layers.iter_mut().filter(|layer| layer.is_shown(gm)) {
+            let empty_color = theme.color(ThemeColor::Lighter).with_alpha(layer.opacity);
+            let full_color = theme.color(ThemeColor::Dark).with_alpha(layer.opacity);
+            layer.meshes.update(
+                &mut layer.tiles,
+                &[empty_color, full_color],
+                |builder, hex, tile| {
+                    tile.build_mesh(
+                        builder,
+                        self.grid.cell_to_world(hex),
+                        self.grid.size,
+                        empty_color,
+                        full_color,
+                        &self.tile_variants,
+                    );
+                },
+            );
+        }
+
+        let terrain_colors = Vec::from_iter(
+            Terrain::ALL
+                .iter()
+                .map(|terrain| theme.terrain_color(*terrain).with_alpha(0.5)),
         );
+        if let Some([hexagon]) = self
+            .tile_variants
+            .get(&Tile::Full)
+            .map(|(_, walls)| walls.as_slice())
+        {
+            self.terrain_meshes.update(
+                &mut self.terrain,
+                &terrain_colors,
+                |builder, hex, terrain| {
+                    if *terrain == Terrain::Normal {
+                        return;
+                    }
+                    let pos = self.grid.cell_to_world(hex);
+                    let vertecies =
+                        Vec::from_iter(hexagon.iter().map(|v| *v * self.grid.size + pos));
+                    builder.fan(&vertecies, terrain_colors[*terrain as usize]);
+                },
+            );
+        }
 
-        for v in 0..6 {
-            {
-                let mut split = [VertexType::Empty; 6];
-                split[v % 6] = VertexType::Both;
-                split[(v + 1) % 6] = VertexType::Full;
-                split[(v + 2) % 6] = VertexType::Both;
-                self.tile_variants.insert(
-                    Tile::Small { rotation: v as u8 },
-                    Tile::split_to_tile_variant(split),
-                );
+        let elevation_colors = [
+            theme.color(ThemeColor::Lighter),
+            theme.color(ThemeColor::Darker),
+        ];
+        if let Some([hexagon]) = self
+            .tile_variants
+            .get(&Tile::Full)
+            .map(|(_, walls)| walls.as_slice())
+        {
+            self.elevation_meshes.update(
+                &mut self.elevation,
+                &elevation_colors,
+                |builder, hex, elevation| {
+                    let shade = (*elevation as f32 * Self::ELEVATION_SHADE).clamp(-0.75, 0.75);
+                    let color = if shade > 0. {
+                        elevation_colors[0].with_alpha(shade)
+                    } else {
+                        elevation_colors[1].with_alpha(-shade)
+                    };
+                    let pos = self.grid.cell_to_world(hex);
+                    let vertecies =
+                        Vec::from_iter(hexagon.iter().map(|v| *v * self.grid.size + pos));
+                    builder.fan(&vertecies, color);
+                },
+            );
+        }
+
+        if let Some(background) = &self.background {
+            background.draw();
+        }
+
+        // Terrain and elevation tint the bottom layer, everything above is drawn over it
+        let viewport = Viewport::from_camera(camera);
+        let mut layers = self.layers.iter().filter(|layer| layer.is_shown(gm));
+        if let Some(layer) = layers.next() {
+            self.draw_chunk_meshes(layer.tiles.chunk_coords(), &layer.meshes, &viewport);
+        }
+        self.draw_chunk_meshes(self.terrain.chunk_coords(), &self.terrain_meshes, &viewport);
+        self.draw_chunk_meshes(
+            self.elevation.chunk_coords(),
+            &self.elevation_meshes,
+            &viewport,
+        );
+        for layer in layers {
+            self.draw_chunk_meshes(layer.tiles.chunk_coords(), &layer.meshes, &viewport);
+        }
+
+        for light in self.lights.iter() {
+            light.draw(
+                self.grid.cell_to_world(light.hex),
+                self.grid.inradius() * 2.,
+            );
+        }
+        self.draw_edges(theme, &viewport, gm);
+    }
+
+    pub fn draw_edges(&self, theme: &Theme, viewport: &Viewport, gm: bool) {
+        for (edge, feature) in self.edges.iter() {
+            if feature.is_hidden(gm) || !self.hex_in_viewport(edge.hex, viewport) {
+                continue;
             }
-            {
-                let mut split = [VertexType::Empty; 6];
-                split[v % 6] = VertexType::Both;
-                split[(v + 1) % 6] = VertexType::Full;
-                split[(v + 2) % 6] = VertexType::Full;
-                split[(v + 3) % 6] = VertexType::Both;
-                self.tile_variants.insert(
-                    Tile::Half { rotation: v as u8 },
-                    Tile::split_to_tile_variant(split),
-                );
+            let color = match feature.kind {
+                EdgeKind::Door | EdgeKind::Portcullis => theme.color(ThemeColor::Darker),
+                EdgeKind::Window => theme.color(ThemeColor::Light),
+                EdgeKind::SecretDoor => theme.color(ThemeColor::Normal),
+            };
+            let (from, to) = edge.endpoints(&self.grid);
+            feature.draw(from, to, color);
+        }
+    }
+
+    fn draw_chunk_meshes<'a>(
+        &self,
+        coords: impl Iterator<Item = &'a ChunkCoord>,
+        meshes: &ChunkMeshes,
+        viewport: &Viewport,
+    ) {
+        for coord in coords {
+            if !self.chunk_in_viewport(coord, viewport) {
+                continue;
             }
-            {
-                let mut split = [VertexType::Full; 6];
-                split[v % 6] = VertexType::Both;
-                split[(v + 1) % 6] = VertexType::Empty;
-                split[(v + 2) % 6] = VertexType::Both;
-                self.tile_variants.insert(
-                    Tile::Large { rotation: v as u8 },
-                    Tile::split_to_tile_variant(split),
-                );
+            for mesh in meshes.get(coord).into_iter().flatten() {
+                draw_mesh(mesh);
             }
         }
+    }
 
-        self.tile_variants.insert(
-            Tile::Full,
-            Tile::split_to_tile_variant([VertexType::Full; 6]),
-        );
+    /// Drops cached geometry, e.g. after `grid` changed
+    pub fn invalidate_meshes(&mut self) {
+        for layer in self.layers.iter_mut() {
+            layer.meshes.clear();
+            layer.tiles.mark_all_dirty();
+        }
+        self.terrain_meshes.clear();
+        self.terrain.mark_all_dirty();
+        self.elevation_meshes.clear();
+        self.elevation.mark_all_dirty();
+    }
+
+    /// GM door handling outside of the map editor
+    pub fn handle_events(&mut self, camera: &Camera2D, gm: bool) -> Result<()> {
+        self.map_events.update();
+
+        let toggle_open = self.map_events.pop(&MapEvent::ToggleEdgeOpen);
+        let toggle_lock = self.map_events.pop(&MapEvent::ToggleEdgeLock);
+        if !gm || !(toggle_open || toggle_lock) {
+            return Ok(());
+        }
+
+        let edge =
+            Edge::from_world_pos(&self.grid, camera.screen_to_world(mouse_position().into()));
+        if let Some(feature) = self.edges.get_mut(&edge) {
+            if toggle_open {
+                feature.toggle_open();
+            }
+            if toggle_lock {
+                feature.toggle_lock();
+            }
+        }
         Ok(())
     }
-    pub fn draw(&mut self, theme: &Theme) {
-        for (hex, tile) in self.tiles.iter() {
-            let pos = h2q(self.hex_layout.hex_to_world_pos(*hex));
-            tile.draw(
-                pos,
-                self.hex_size,
-                theme.color(ThemeColor::Lighter),
-                theme.color(ThemeColor::Dark),
-                &self.tile_variants,
-            );
+
+    /// Whether crossing from `hex` towards its neighbour in `direction` is blocked by an edge feature
+    pub fn edge_blocks_movement(&self, hex: Hex, direction: u8) -> bool {
+        self.edges
+            .get(&Edge::new(&self.grid, hex, direction))
+            .is_some_and(|feature| feature.blocks_movement())
+    }
+
+    pub fn edge_blocks_sight(&self, hex: Hex, direction: u8) -> bool {
+        self.edges
+            .get(&Edge::new(&self.grid, hex, direction))
+            .is_some_and(|feature| feature.blocks_sight())
+    }
+
+    pub fn elevation_at(&self, hex: Hex) -> i32 {
+        self.elevation.get(&hex).copied().unwrap_or_default()
+    }
+
+    pub fn set_elevation(&mut self, hex: Hex, elevation: i32) {
+        if elevation == 0 {
+            self.elevation.remove(&hex);
+        } else {
+            self.elevation.insert(hex, elevation);
+        }
+    }
+
+    /// Distance between cell centers in `grid_scale` units, including the height difference
+    pub fn distance_3d(&self, a: Hex, b: Hex) -> f32 {
+        let horizontal = self.grid.distance(a, b) as f32;
+        let vertical = (self.elevation_at(a) - self.elevation_at(b)) as f32;
+        horizontal.hypot(vertical) * self.grid_scale
+    }
+
+    pub fn terrain_at(&self, hex: Hex) -> Terrain {
+        self.terrain.get(&hex).copied().unwrap_or_default()
+    }
+
+    pub fn set_terrain(&mut self, hex: Hex, terrain: Terrain) {
+        if terrain == Terrain::Normal {
+            self.terrain.remove(&hex);
+        } else {
+            self.terrain.insert(hex, terrain);
+        }
+    }
+
+    pub fn movement_cost(&self, hex: Hex) -> u32 {
+        self.terrain_at(hex).movement_cost()
+    }
+
+    pub fn blocks_sight(&self, hex: Hex) -> bool {
+        self.terrain_at(hex).blocks_sight()
+    }
+
+    pub fn blocks_movement(&self, hex: Hex) -> bool {
+        self.terrain_at(hex).blocks_movement()
+    }
+
+    /// World space bounding box of a chunk, including the cell corners
+    pub fn chunk_bounds(&self, coord: &ChunkCoord) -> (Vec2, Vec2) {
+        let mut min = Vec2::splat(f32::INFINITY);
+        let mut max = Vec2::splat(f32::NEG_INFINITY);
+        for hex in coord.corner_hexes() {
+            let pos = self.grid.cell_to_world(hex);
+            min = min.min(pos);
+            max = max.max(pos);
+        }
+        (
+            min - self.grid.circumradius(),
+            max + self.grid.circumradius(),
+        )
+    }
+
+    pub fn chunk_in_viewport(&self, coord: &ChunkCoord, viewport: &Viewport) -> bool {
+        let (min, max) = self.chunk_bounds(coord);
+        viewport.intersects_rect(min, max)
+    }
+
+    pub fn hex_in_viewport(&self, hex: Hex, viewport: &Viewport) -> bool {
+        let pos = self.grid.cell_to_world(hex);
+        let r = self.grid.circumradius();
+        viewport.intersects_rect(pos - r, pos + r)
+    }
+
+    /// Hexes holding a tile on any shown layer that are (at least partly) on screen
+    pub fn visible_hexes(&self, camera: &Camera2D, gm: bool) -> HashSet<Hex> {
+        let viewport = Viewport::from_camera(camera);
+        let mut hexes = HashSet::new();
+        for layer in self.layers.iter().filter(|layer| layer.is_shown(gm)) {
+            for (coord, chunk) in layer.tiles.chunks() {
+                if !self.chunk_in_viewport(coord, &viewport) {
+                    continue;
+                }
+                hexes.extend(
+                    chunk
+                        .keys()
+                        .filter(|hex| self.hex_in_viewport(**hex, &viewport)),
+                );
+            }
         }
+        hexes
     }
 }
 
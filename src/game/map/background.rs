@@ -0,0 +1,93 @@
+use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Image drawn below every layer
+#[derive(Debug, Clone)]
+pub struct Background {
+    pub image: String,
+    /// World position of the top left corner
+    pub offset: Vec2,
+    /// World units per image pixel
+    pub scale: f32,
+    /// Radians, around the top left corner
+    pub rotation: f32,
+    pub texture: Option<Texture2D>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BackgroundData {
+    pub image: String,
+    pub offset: (f32, f32),
+    pub scale: f32,
+    #[serde(default)]
+    pub rotation: f32,
+}
+
+impl Background {
+    pub fn new(image: &str, offset: Vec2) -> Self {
+        Self {
+            image: image.to_string(),
+            offset,
+            scale: 1.,
+            rotation: 0.,
+            texture: None,
+        }
+    }
+
+    pub async fn load_texture(&mut self) {
+        self.texture = load_texture(&self.image).await.ok();
+    }
+
+    pub fn draw(&self) {
+        let Some(texture) = &self.texture else {
+            return;
+        };
+        draw_texture_ex(
+            texture,
+            self.offset.x,
+            self.offset.y,
+            WHITE,
+            DrawTextureParams {
+                dest_size: Some(texture.size() * self.scale),
+                rotation: self.rotation,
+                pivot: Some(self.offset),
+                ..Default::default()
+            },
+        );
+    }
+
+    /// Scales the image around a world position
+    pub fn scale_around(&mut self, pivot: Vec2, factor: f32) {
+        self.offset = pivot + (self.offset - pivot) * factor;
+        self.scale *= factor;
+    }
+
+    /// Turns the image by `angle` radians around a world position
+    pub fn rotate_around(&mut self, pivot: Vec2, angle: f32) {
+        self.offset = pivot + Vec2::from_angle(angle).rotate(self.offset - pivot);
+        self.rotation += angle;
+    }
+}
+
+impl From<&Background> for BackgroundData {
+    fn from(background: &Background) -> Self {
+        Self {
+            image: background.image.clone(),
+            offset: background.offset.into(),
+            scale: background.scale,
+            rotation: background.rotation,
+        }
+    }
+}
+
+impl From<BackgroundData> for Background {
+    fn from(data: BackgroundData) -> Self {
+        Self {
+            image: data.image,
+            offset: data.offset.into(),
+            scale: data.scale,
+            rotation: data.rotation,
+            texture: None,
+        }
+    }
+}
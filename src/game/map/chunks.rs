@@ -0,0 +1,207 @@
+use std::{
+    collections::{HashMap, HashSet, hash_map},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use hexx::Hex;
+use macroquad::prelude::*;
+
+pub const CHUNK_SIZE: i32 = 16;
+
+/// Parallelogram of `CHUNK_SIZE` x `CHUNK_SIZE` hexes in axial coordinates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ChunkCoord {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl ChunkCoord {
+    pub fn of(hex: Hex) -> Self {
+        Self {
+            x: hex.x.div_euclid(CHUNK_SIZE),
+            y: hex.y.div_euclid(CHUNK_SIZE),
+        }
+    }
+
+    pub fn corner_hexes(&self) -> [Hex; 4] {
+        let min = Hex::new(self.x * CHUNK_SIZE, self.y * CHUNK_SIZE);
+        let d = CHUNK_SIZE - 1;
+        [
+            min,
+            min + Hex::new(d, 0),
+            min + Hex::new(d, d),
+            min + Hex::new(0, d),
+        ]
+    }
+}
+
+/// Revisions handed out to every `Chunks`, so no two states of any of them share one
+static REVISIONS: AtomicU64 = AtomicU64::new(0);
+
+fn next_revision() -> u64 {
+    REVISIONS.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Hex keyed storage split into spatial chunks
+#[derive(Debug, Clone)]
+pub struct Chunks<T> {
+    chunks: HashMap<ChunkCoord, HashMap<Hex, T>>,
+    len: usize,
+    dirty: HashSet<ChunkCoord>,
+    revision: u64,
+}
+
+impl<T> Default for Chunks<T> {
+    fn default() -> Self {
+        Self {
+            chunks: HashMap::new(),
+            len: 0,
+            dirty: HashSet::new(),
+            revision: next_revision(),
+        }
+    }
+}
+
+impl<T> FromIterator<(Hex, T)> for Chunks<T> {
+    fn from_iter<I: IntoIterator<Item = (Hex, T)>>(iter: I) -> Self {
+        let mut chunks = Self::default();
+        for (hex, value) in iter {
+            chunks.insert(hex, value);
+        }
+        chunks
+    }
+}
+
+impl<T> Chunks<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Changes whenever a cell is written or removed, unlike the dirty chunks taken by meshing
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    pub fn clear(&mut self) {
+        self.revision = next_revision();
+        self.dirty.extend(self.chunks.keys());
+        self.chunks.clear();
+        self.len = 0;
+    }
+
+    pub fn get(&self, hex: &Hex) -> Option<&T> {
+        self.chunks.get(&ChunkCoord::of(*hex))?.get(hex)
+    }
+
+    pub fn contains_key(&self, hex: &Hex) -> bool {
+        self.get(hex).is_some()
+    }
+
+    pub fn insert(&mut self, hex: Hex, value: T) -> Option<T> {
+        let coord = ChunkCoord::of(hex);
+        self.revision = next_revision();
+        self.dirty.insert(coord);
+        let old = self.chunks.entry(coord).or_default().insert(hex, value);
+        if old.is_none() {
+            self.len += 1;
+        }
+        old
+    }
+
+    pub fn remove(&mut self, hex: &Hex) -> Option<T> {
+        let coord = ChunkCoord::of(*hex);
+        let chunk = self.chunks.get_mut(&coord)?;
+        let old = chunk.remove(hex);
+        if chunk.is_empty() {
+            self.chunks.remove(&coord);
+        }
+        if old.is_some() {
+            self.revision = next_revision();
+            self.dirty.insert(coord);
+            self.len -= 1;
+        }
+        old
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Hex, &T)> {
+        self.chunks.values().flat_map(|chunk| chunk.iter())
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &Hex> {
+        self.iter().map(|(hex, _)| hex)
+    }
+
+    pub fn chunk_coords(&self) -> impl Iterator<Item = &ChunkCoord> {
+        self.chunks.keys()
+    }
+
+    pub fn chunk(&self, coord: &ChunkCoord) -> Option<&HashMap<Hex, T>> {
+        self.chunks.get(coord)
+    }
+
+    /// Chunks modified since the last call
+    pub fn take_dirty(&mut self) -> HashSet<ChunkCoord> {
+        std::mem::take(&mut self.dirty)
+    }
+
+    pub fn mark_all_dirty(&mut self) {
+        self.dirty.extend(self.chunks.keys());
+    }
+
+    pub fn chunks(&self) -> hash_map::Iter<'_, ChunkCoord, HashMap<Hex, T>> {
+        self.chunks.iter()
+    }
+}
+
+/// World space quad seen through a (possibly rotated) camera
+#[derive(Debug, Clone, Copy)]
+pub struct Viewport {
+    pub corners: [Vec2; 4],
+}
+
+impl Viewport {
+    pub fn from_camera(camera: &Camera2D) -> Self {
+        let (w, h) = (screen_width(), screen_height());
+        Self {
+            corners: [
+                camera.screen_to_world(vec2(0., 0.)),
+                camera.screen_to_world(vec2(w, 0.)),
+                camera.screen_to_world(vec2(w, h)),
+                camera.screen_to_world(vec2(0., h)),
+            ],
+        }
+    }
+
+    /// Separating axis test between the viewport quad and an axis aligned rect
+    pub fn intersects_rect(&self, min: Vec2, max: Vec2) -> bool {
+        let rect = [min, vec2(max.x, min.y), max, vec2(min.x, max.y)];
+        let axes = [
+            Vec2::X,
+            Vec2::Y,
+            (self.corners[1] - self.corners[0]).perp(),
+            (self.corners[3] - self.corners[0]).perp(),
+        ];
+        axes.into_iter().all(|axis| {
+            let (a_min, a_max) = project(&self.corners, axis);
+            let (b_min, b_max) = project(&rect, axis);
+            a_min <= b_max && b_min <= a_max
+        })
+    }
+}
+
+fn project(points: &[Vec2; 4], axis: Vec2) -> (f32, f32) {
+    points
+        .iter()
+        .map(|p| p.dot(axis))
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), d| {
+            (min.min(d), max.max(d))
+        })
+}
@@ -0,0 +1,610 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    hash::Hash,
+};
+
+use anyhow::{Result, bail};
+use hexx::Hex;
+
+use crate::game::map::{Map, chunks::Chunks, edge::Edge, layer::Layer, tile::Tile};
+
+/// A cell holding a different tile in two maps
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TileChange {
+    pub layer: String,
+    pub hex: Hex,
+    pub before: Option<Tile>,
+    pub after: Option<Tile>,
+}
+
+impl fmt::Display for TileChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (x, y) = (self.hex.x, self.hex.y);
+        let (before, after) = (tile_name(self.before), tile_name(self.after));
+        match (self.before, self.after) {
+            (None, _) => write!(f, "+ {} ({x}, {y}) {after}", self.layer),
+            (_, None) => write!(f, "- {} ({x}, {y}) {before}", self.layer),
+            _ => write!(f, "~ {} ({x}, {y}) {before} -> {after}", self.layer),
+        }
+    }
+}
+
+fn tile_name(tile: Option<Tile>) -> String {
+    tile.map_or("nothing".into(), |tile| format!("{tile:?}"))
+}
+
+/// Something both sides of a three-way merge changed differently, resolved as ours
+#[derive(Debug, Clone, PartialEq)]
+pub enum Conflict {
+    Tile {
+        layer: String,
+        hex: Hex,
+        base: Option<Tile>,
+        ours: Option<Tile>,
+        theirs: Option<Tile>,
+    },
+    /// A setting of a layer, by the name `base` knows it by
+    Layer {
+        layer: String,
+        property: &'static str,
+    },
+    Terrain(Hex),
+    Elevation(Hex),
+    Edge(Edge),
+    Light(Hex),
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Conflict::Tile {
+                layer,
+                hex,
+                base,
+                ours,
+                theirs,
+            } => write!(
+                f,
+                "conflict: tile at ({}, {}) on {layer}: base {}, ours {}, theirs {}",
+                hex.x,
+                hex.y,
+                tile_name(*base),
+                tile_name(*ours),
+                tile_name(*theirs)
+            ),
+            Conflict::Layer { layer, property } => {
+                write!(f, "conflict: {property} of layer {layer}")
+            }
+            Conflict::Terrain(hex) => write!(f, "conflict: terrain at ({}, {})", hex.x, hex.y),
+            Conflict::Elevation(hex) => write!(f, "conflict: height at ({}, {})", hex.x, hex.y),
+            Conflict::Edge(edge) => write!(
+                f,
+                "conflict: edge {} of ({}, {})",
+                edge.direction, edge.hex.x, edge.hex.y
+            ),
+            Conflict::Light(hex) => write!(f, "conflict: light at ({}, {})", hex.x, hex.y),
+        }
+    }
+}
+
+/// Takes whichever side changed a value since `base`, and ours when both did differently.
+/// Returns the merged value and whether both sides changed it.
+fn pick<V: PartialEq>(base: V, ours: V, theirs: V) -> (V, bool) {
+    if ours == theirs || theirs == base {
+        (ours, false)
+    } else if ours == base {
+        (theirs, false)
+    } else {
+        (ours, true)
+    }
+}
+
+/// Merges every entry as `pick` does. Returns the merged entries and the keys both sides
+/// changed.
+fn three_way<K, V>(
+    base: &HashMap<K, V>,
+    ours: &HashMap<K, V>,
+    theirs: &HashMap<K, V>,
+) -> (HashMap<K, V>, Vec<K>)
+where
+    K: Copy + Eq + Hash,
+    V: Copy + PartialEq,
+{
+    let keys =
+        HashSet::<K>::from_iter(base.keys().chain(ours.keys()).chain(theirs.keys()).copied());
+    let mut merged = HashMap::new();
+    let mut conflicts = Vec::new();
+    for key in keys {
+        let (value, conflict) = pick(base.get(&key), ours.get(&key), theirs.get(&key));
+        if conflict {
+            conflicts.push(key);
+        }
+        if let Some(value) = value {
+            merged.insert(key, *value);
+        }
+    }
+    (merged, conflicts)
+}
+
+/// `pick` for a setting of the layer `base` knows as `layer`, noting when both sides changed it
+fn merge_setting<V: PartialEq + Clone>(
+    layer: &str,
+    property: &'static str,
+    [base, ours, theirs]: [&V; 3],
+    conflicts: &mut Vec<Conflict>,
+) -> V {
+    let (value, conflict) = pick(base, ours, theirs);
+    if conflict {
+        conflicts.push(Conflict::Layer {
+            layer: layer.to_string(),
+            property,
+        });
+    }
+    value.clone()
+}
+
+fn cells<T: Copy>(chunks: &Chunks<T>) -> HashMap<Hex, T> {
+    HashMap::from_iter(chunks.iter().map(|(hex, value)| (*hex, *value)))
+}
+
+fn sorted_hexes(mut hexes: Vec<Hex>) -> Vec<Hex> {
+    hexes.sort_by_key(|hex| (hex.y, hex.x));
+    hexes
+}
+
+impl Map {
+    /// Tiles of the layer called `name`, none when there is no such layer
+    fn layer_cells(&self, name: &str) -> HashMap<Hex, Tile> {
+        self.layers
+            .iter()
+            .find(|layer| layer.name == name)
+            .map(|layer| cells(&layer.tiles))
+            .unwrap_or_default()
+    }
+
+    /// Names of the layers of this map, then those only `other` has
+    fn layer_names(&self, other: &Map) -> Vec<String> {
+        let mut names = Vec::from_iter(self.layers.iter().map(|layer| layer.name.clone()));
+        for layer in other.layers.iter() {
+            if !names.contains(&layer.name) {
+                names.push(layer.name.clone());
+            }
+        }
+        names
+    }
+
+    /// Index of the layer `base` knew as `name`: the one still called so, or the one in its
+    /// place with a name `base` does not have, as it was renamed
+    fn layer_known_as(&self, base: &Map, name: &str) -> Option<usize> {
+        let in_base = |name: &str| base.layers.iter().any(|layer| layer.name == name);
+        self.layers
+            .iter()
+            .position(|layer| layer.name == name)
+            .or_else(|| {
+                let i = base.layers.iter().position(|layer| layer.name == name)?;
+                self.layers
+                    .get(i)
+                    .filter(|layer| !in_base(&layer.name))
+                    .map(|_| i)
+            })
+    }
+
+    /// Names `base` knows the layers of this map and of `theirs` by, new layers keeping
+    /// their own; ours first, then those only theirs has
+    fn base_layer_names(&self, base: &Map, theirs: &Map) -> Vec<String> {
+        let mut names = Vec::new();
+        for side in [self, theirs] {
+            for (i, layer) in side.layers.iter().enumerate() {
+                let name = base
+                    .layers
+                    .iter()
+                    .map(|known| &known.name)
+                    .find(|known| side.layer_known_as(base, known) == Some(i))
+                    .unwrap_or(&layer.name);
+                if !names.contains(name) {
+                    names.push(name.clone());
+                }
+            }
+        }
+        names
+    }
+
+    /// The tiles `other` changed compared to this map, with layers matched by name
+    pub fn diff(&self, other: &Map) -> Vec<TileChange> {
+        let mut changes = Vec::new();
+        for name in self.layer_names(other) {
+            let (before, after) = (self.layer_cells(&name), other.layer_cells(&name));
+            let hexes = HashSet::<Hex>::from_iter(before.keys().chain(after.keys()).copied());
+            for hex in sorted_hexes(Vec::from_iter(hexes)) {
+                let (before, after) = (before.get(&hex).copied(), after.get(&hex).copied());
+                if before != after {
+                    changes.push(TileChange {
+                        layer: name.clone(),
+                        hex,
+                        before,
+                        after,
+                    });
+                }
+            }
+        }
+        changes
+    }
+
+    /// Applies the changes `theirs` made since `base` to this map, the ours side.
+    ///
+    /// Tiles, terrain, heights, edges and lights are merged entry by entry, and the name,
+    /// visibility, opacity and gm only setting of each layer like entries too; a renamed
+    /// layer is the one left in the place of a layer `base` had. Everything else stays ours.
+    /// Entries both sides changed differently keep ours and are returned.
+    pub fn merge_three_way(&mut self, base: &Map, theirs: &Map) -> Result<Vec<Conflict>> {
+        for other in [base, theirs] {
+            if other.grid.kind != self.grid.kind {
+                bail!(
+                    "cannot merge a {:?} map into a {:?} map",
+                    other.grid.kind,
+                    self.grid.kind
+                );
+            }
+        }
+        let mut conflicts = Vec::new();
+
+        for name in self.base_layer_names(base, theirs) {
+            let (ours, theirs_index) = (
+                self.layer_known_as(base, &name),
+                theirs.layer_known_as(base, &name),
+            );
+            let layers = [
+                base.layers.iter().find(|layer| layer.name == name),
+                ours.map(|i| &self.layers[i]),
+                theirs_index.map(|i| &theirs.layers[i]),
+            ];
+            let sides =
+                layers.map(|layer| layer.map(|layer| cells(&layer.tiles)).unwrap_or_default());
+            let [b, o, t] = &sides;
+            let (tiles, hexes) = three_way(b, o, t);
+            for hex in sorted_hexes(hexes) {
+                let [base, ours, theirs] = sides.each_ref().map(|side| side.get(&hex).copied());
+                conflicts.push(Conflict::Tile {
+                    layer: name.clone(),
+                    hex,
+                    base,
+                    ours,
+                    theirs,
+                });
+            }
+            let tiles = Chunks::from_iter(tiles);
+            let settings = match layers {
+                [Some(b), Some(o), Some(t)] => Some((
+                    merge_setting(&name, "name", [&b.name, &o.name, &t.name], &mut conflicts),
+                    merge_setting(
+                        &name,
+                        "visibility",
+                        [&b.visible, &o.visible, &t.visible],
+                        &mut conflicts,
+                    ),
+                    merge_setting(
+                        &name,
+                        "opacity",
+                        [&b.opacity, &o.opacity, &t.opacity],
+                        &mut conflicts,
+                    ),
+                    merge_setting(
+                        &name,
+                        "gm only",
+                        [&b.gm_only, &o.gm_only, &t.gm_only],
+                        &mut conflicts,
+                    ),
+                )),
+                _ => None,
+            };
+            let in_base = layers[0].is_some();
+            match ours {
+                Some(i) => {
+                    let layer = &mut self.layers[i];
+                    layer.tiles = tiles;
+                    if let Some((name, visible, opacity, gm_only)) = settings {
+                        layer.name = name;
+                        layer.visible = visible;
+                        layer.opacity = opacity;
+                        layer.gm_only = gm_only;
+                    }
+                }
+                // Layers we removed stay removed
+                None if in_base => (),
+                None => {
+                    if let Some(i) = theirs_index {
+                        self.layers.push(Layer {
+                            tiles,
+                            meshes: Default::default(),
+                            ..theirs.layers[i].clone()
+                        });
+                    }
+                }
+            }
+        }
+
+        let (terrain, hexes) = three_way(
+            &cells(&base.terrain),
+            &cells(&self.terrain),
+            &cells(&theirs.terrain),
+        );
+        self.terrain = Chunks::from_iter(terrain);
+        conflicts.extend(sorted_hexes(hexes).into_iter().map(Conflict::Terrain));
+
+        let (elevation, hexes) = three_way(
+            &cells(&base.elevation),
+            &cells(&self.elevation),
+            &cells(&theirs.elevation),
+        );
+        self.elevation = Chunks::from_iter(elevation);
+        conflicts.extend(sorted_hexes(hexes).into_iter().map(Conflict::Elevation));
+
+        let (edges, mut keys) = three_way(&base.edges, &self.edges, &theirs.edges);
+        self.edges = edges;
+        keys.sort_by_key(|edge| (edge.hex.y, edge.hex.x, edge.direction));
+        conflicts.extend(keys.into_iter().map(Conflict::Edge));
+
+        let lights =
+            |map: &Map| HashMap::from_iter(map.lights.iter().map(|light| (light.hex, *light)));
+        let (mut lights, hexes) = three_way(&lights(base), &lights(self), &lights(theirs));
+        // Ours keep their order, the lights theirs added go last
+        let mut merged = Vec::from_iter(
+            self.lights
+                .iter()
+                .filter_map(|light| lights.remove(&light.hex)),
+        );
+        for hex in sorted_hexes(Vec::from_iter(lights.keys().copied())) {
+            merged.extend(lights.remove(&hex));
+        }
+        self.lights = merged;
+        conflicts.extend(sorted_hexes(hexes).into_iter().map(Conflict::Light));
+
+        self.active_layer = self.active_layer.min(self.layers.len().saturating_sub(1));
+        self.invalidate_meshes();
+        Ok(conflicts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::map::light::LightSource;
+
+    fn entries(pairs: &[(i32, u8)]) -> HashMap<i32, u8> {
+        HashMap::from_iter(pairs.iter().copied())
+    }
+
+    fn map(tiles: &[(i32, i32, Tile)]) -> Map {
+        let mut map = Map::default();
+        map.build_tile_variants();
+        for (x, y, tile) in tiles {
+            map.layers[0].tiles.insert(Hex::new(*x, *y), *tile);
+        }
+        map
+    }
+
+    fn light(x: i32, intensity: f32) -> LightSource {
+        LightSource {
+            hex: Hex::new(x, 0),
+            range: 4.,
+            intensity,
+            color: 0xffffff,
+        }
+    }
+
+    #[test]
+    fn one_sided_changes_are_taken() {
+        let base = entries(&[(1, 1), (2, 2), (3, 3)]);
+        let ours = entries(&[(1, 10), (2, 2), (3, 3)]);
+        let theirs = entries(&[(1, 1), (2, 20), (4, 4)]);
+        let (merged, conflicts) = three_way(&base, &ours, &theirs);
+        assert_eq!(merged, entries(&[(1, 10), (2, 20), (4, 4)]));
+        assert!(conflicts.is_empty());
+    }
+
+    #[test]
+    fn same_changes_on_both_sides_agree() {
+        let base = entries(&[(1, 1), (2, 2)]);
+        let both = entries(&[(1, 10), (3, 3)]);
+        let (merged, conflicts) = three_way(&base, &both, &both);
+        assert_eq!(merged, both);
+        assert!(conflicts.is_empty());
+    }
+
+    #[test]
+    fn different_changes_conflict_and_keep_ours() {
+        let base = entries(&[(1, 1), (2, 2)]);
+        let ours = entries(&[(1, 10), (3, 30)]);
+        let theirs = entries(&[(1, 11), (2, 22), (3, 31)]);
+        let (merged, mut conflicts) = three_way(&base, &ours, &theirs);
+        conflicts.sort();
+        // 2 was deleted by ours and changed by theirs
+        assert_eq!(conflicts, [1, 2, 3]);
+        assert_eq!(merged, ours);
+    }
+
+    #[test]
+    fn delete_against_unchanged_deletes() {
+        let base = entries(&[(1, 1), (2, 2)]);
+        let ours = entries(&[(2, 2)]);
+        let theirs = entries(&[(1, 1)]);
+        let (merged, conflicts) = three_way(&base, &ours, &theirs);
+        assert!(merged.is_empty());
+        assert!(conflicts.is_empty());
+    }
+
+    #[test]
+    fn tile_conflicts_report_all_three_sides() {
+        let base = map(&[(0, 0, Tile::Empty), (1, 0, Tile::Full)]);
+        let mut ours = map(&[(0, 0, Tile::Full), (1, 0, Tile::Full)]);
+        let theirs = map(&[(0, 0, Tile::Half { rotation: 1 }), (2, 0, Tile::Empty)]);
+        let conflicts = ours.merge_three_way(&base, &theirs).unwrap();
+        assert_eq!(
+            conflicts,
+            [Conflict::Tile {
+                layer: "floor".into(),
+                hex: Hex::ZERO,
+                base: Some(Tile::Empty),
+                ours: Some(Tile::Full),
+                theirs: Some(Tile::Half { rotation: 1 }),
+            }]
+        );
+        let tiles = &ours.layers[0].tiles;
+        assert_eq!(tiles.get(&Hex::ZERO), Some(&Tile::Full));
+        assert_eq!(tiles.get(&Hex::new(1, 0)), None);
+        assert_eq!(tiles.get(&Hex::new(2, 0)), Some(&Tile::Empty));
+    }
+
+    #[test]
+    fn layers_added_by_theirs_are_added() {
+        let base = map(&[]);
+        let mut ours = map(&[]);
+        let mut theirs = map(&[]);
+        let mut traps = Layer::new_gm_only("traps");
+        traps.tiles.insert(Hex::ZERO, Tile::Full);
+        theirs.layers.push(traps);
+        assert!(ours.merge_three_way(&base, &theirs).unwrap().is_empty());
+        let added = ours.layers.last().unwrap();
+        assert_eq!(added.name, "traps");
+        assert!(added.gm_only);
+        assert_eq!(added.tiles.get(&Hex::ZERO), Some(&Tile::Full));
+    }
+
+    fn without_layer(from: &Map, name: &str) -> Map {
+        let mut map = map(&[]);
+        map.layers = Vec::from_iter(
+            from.layers
+                .iter()
+                .filter(|layer| layer.name != name)
+                .cloned(),
+        );
+        map
+    }
+
+    #[test]
+    fn deleted_layers_lose_their_unchanged_tiles() {
+        let mut base = map(&[]);
+        base.layers[2].tiles.insert(Hex::ZERO, Tile::Full);
+
+        // Ours removed the decoration layer, theirs left it alone
+        let mut ours = without_layer(&base, "decoration");
+        let conflicts = ours.merge_three_way(&base, &without_layer(&base, ""));
+        assert!(conflicts.unwrap().is_empty());
+        assert!(ours.layers.iter().all(|layer| layer.name != "decoration"));
+
+        // Theirs removed it, ours left it alone
+        let mut ours = without_layer(&base, "");
+        let conflicts = ours.merge_three_way(&base, &without_layer(&base, "decoration"));
+        assert!(conflicts.unwrap().is_empty());
+        assert!(ours.layers[2].tiles.is_empty());
+    }
+
+    #[test]
+    fn deleted_layers_edited_on_the_other_side_conflict() {
+        let mut base = map(&[]);
+        base.layers[2].tiles.insert(Hex::ZERO, Tile::Full);
+        let mut theirs = without_layer(&base, "");
+        theirs.layers[2].tiles.insert(Hex::ZERO, Tile::Empty);
+        let mut ours = without_layer(&base, "decoration");
+        let conflicts = ours.merge_three_way(&base, &theirs).unwrap();
+        assert_eq!(
+            conflicts,
+            [Conflict::Tile {
+                layer: "decoration".into(),
+                hex: Hex::ZERO,
+                base: Some(Tile::Full),
+                ours: None,
+                theirs: Some(Tile::Empty),
+            }]
+        );
+    }
+
+    #[test]
+    fn lights_merge_by_hex() {
+        let mut base = map(&[]);
+        base.lights = vec![light(0, 1.), light(1, 1.)];
+        let mut ours = map(&[]);
+        ours.lights = vec![light(1, 1.), light(0, 0.5)];
+        let mut theirs = map(&[]);
+        theirs.lights = vec![light(0, 1.), light(1, 2.), light(2, 1.)];
+        assert!(ours.merge_three_way(&base, &theirs).unwrap().is_empty());
+        // Ours keep their order, theirs changed the light at 1 and added the one at 2
+        assert_eq!(ours.lights, [light(1, 2.), light(0, 0.5), light(2, 1.)]);
+
+        let mut both = map(&[]);
+        both.lights = vec![light(0, 0.5)];
+        let mut other = map(&[]);
+        other.lights = vec![light(0, 3.)];
+        let conflicts = both.merge_three_way(&base, &other).unwrap();
+        assert_eq!(conflicts, [Conflict::Light(Hex::ZERO)]);
+        assert_eq!(both.lights, [light(0, 0.5)]);
+    }
+
+    #[test]
+    fn layer_settings_merge_like_entries() {
+        let base = map(&[]);
+        let mut ours = map(&[]);
+        ours.layers[1].gm_only = true;
+        ours.layers[2].opacity = 0.5;
+        let mut theirs = map(&[]);
+        theirs.layers[1].visible = false;
+        theirs.layers[1].opacity = 0.25;
+        theirs.layers[2].opacity = 0.75;
+        let conflicts = ours.merge_three_way(&base, &theirs).unwrap();
+        assert_eq!(
+            conflicts,
+            [Conflict::Layer {
+                layer: "decoration".into(),
+                property: "opacity",
+            }]
+        );
+        let walls = &ours.layers[1];
+        assert!(walls.gm_only && !walls.visible);
+        assert_eq!(walls.opacity, 0.25);
+        assert_eq!(ours.layers[2].opacity, 0.5);
+    }
+
+    #[test]
+    fn renamed_layers_keep_the_tiles_of_both_sides() {
+        let base = map(&[]);
+        let mut ours = map(&[]);
+        ours.layers[2].tiles.insert(Hex::ZERO, Tile::Full);
+        let mut theirs = map(&[]);
+        theirs.layers[2].name = "props".into();
+        theirs.layers[2].tiles.insert(Hex::new(1, 0), Tile::Full);
+        assert!(ours.merge_three_way(&base, &theirs).unwrap().is_empty());
+        let names = Vec::from_iter(ours.layers.iter().map(|layer| layer.name.as_str()));
+        assert_eq!(names, ["floor", "walls", "props", "gm"]);
+        assert_eq!(ours.layers[2].tiles.len(), 2);
+
+        // Renamed on both sides differently
+        let mut both = map(&[]);
+        both.layers[2].name = "scenery".into();
+        let conflicts = both.merge_three_way(&base, &theirs).unwrap();
+        assert_eq!(
+            conflicts,
+            [Conflict::Layer {
+                layer: "decoration".into(),
+                property: "name",
+            }]
+        );
+        assert_eq!(both.layers[2].name, "scenery");
+        assert_eq!(both.layers.len(), 4);
+    }
+
+    #[test]
+    fn diff_lists_changes_by_layer() {
+        let before = map(&[(0, 0, Tile::Empty), (1, 0, Tile::Full)]);
+        let after = map(&[(0, 0, Tile::Full), (0, 1, Tile::Empty)]);
+        let changes = Vec::from_iter(before.diff(&after).iter().map(|c| c.to_string()));
+        assert_eq!(
+            changes,
+            [
+                "~ floor (0, 0) Empty -> Full",
+                "- floor (1, 0) Full",
+                "+ floor (0, 1) Empty",
+            ]
+        );
+    }
+}
@@ -0,0 +1,335 @@
+use std::cmp::Ordering;
+
+use anyhow::{Result, bail};
+use hexx::{Hex, HexOrientation};
+use serde::{Deserialize, Serialize};
+
+use crate::game::map::{
+    Map,
+    background::BackgroundData,
+    chunks::Chunks,
+    edge::{Edge, EdgeFeature},
+    generator::Generation,
+    grid::GridKind,
+    layer::Layer,
+    light::LightSource,
+    mesh::ChunkMeshes,
+    terrain::Terrain,
+    tile::Tile,
+};
+
+/// Version written by `MapDocument::to_ron`.
+///
+/// 0: bare `[(Hex, Tile)]` list
+/// 1: list of layers
+/// 2: header + layers
+/// 3: hex orientation replaced by grid kind
+pub const FORMAT_VERSION: u32 = 3;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MapHeader {
+    pub version: u32,
+    pub name: String,
+    pub author: String,
+    pub grid: GridKind,
+    /// Hex circumradius, or square side length
+    pub cell_size: f32,
+    /// World position of the center of cell (0, 0)
+    #[serde(default)]
+    pub origin: (f32, f32),
+    /// Distance one cell represents in game units (e.g. 5 ft)
+    pub grid_scale: f32,
+    /// Generator runs in the order they were applied
+    #[serde(default)]
+    pub generations: Vec<Generation>,
+}
+
+impl Default for MapHeader {
+    fn default() -> Self {
+        Self {
+            version: FORMAT_VERSION,
+            name: String::new(),
+            author: String::new(),
+            grid: GridKind::PointyHex,
+            cell_size: 32.,
+            origin: (0., 0.),
+            grid_scale: 5.,
+            generations: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LayerData {
+    pub name: String,
+    pub visible: bool,
+    pub opacity: f32,
+    pub gm_only: bool,
+    pub tiles: Vec<(Hex, Tile)>,
+}
+
+impl From<&Layer> for LayerData {
+    fn from(layer: &Layer) -> Self {
+        Self {
+            name: layer.name.clone(),
+            visible: layer.visible,
+            opacity: layer.opacity,
+            gm_only: layer.gm_only,
+            tiles: sorted_cells(&layer.tiles),
+        }
+    }
+}
+
+impl From<LayerData> for Layer {
+    fn from(data: LayerData) -> Self {
+        Self {
+            name: data.name,
+            tiles: Chunks::from_iter(data.tiles),
+            visible: data.visible,
+            opacity: data.opacity,
+            gm_only: data.gm_only,
+            meshes: ChunkMeshes::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MapDocument {
+    pub header: MapHeader,
+    pub layers: Vec<LayerData>,
+    #[serde(default)]
+    pub terrain: Vec<(Hex, Terrain)>,
+    #[serde(default)]
+    pub elevation: Vec<(Hex, i32)>,
+    #[serde(default)]
+    pub edges: Vec<(Edge, EdgeFeature)>,
+    #[serde(default)]
+    pub lights: Vec<LightSource>,
+    #[serde(default)]
+    pub background: Option<BackgroundData>,
+}
+
+impl MapDocument {
+    /// Parses any known format version and migrates it to `FORMAT_VERSION`.
+    pub fn from_ron(data: &str) -> Result<Self> {
+        let mut versioned = Versioned::parse(data)?;
+        while !matches!(versioned, Versioned::V3(_)) {
+            versioned = versioned.upgrade();
+        }
+        match versioned {
+            Versioned::V3(document) => Ok(document),
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn to_ron(&self) -> Result<String> {
+        Ok(ron::ser::to_string_pretty(
+            self,
+            ron::ser::PrettyConfig::default().compact_structs(true),
+        )?)
+    }
+}
+
+#[derive(Deserialize)]
+struct VersionProbe {
+    header: HeaderVersionProbe,
+}
+
+#[derive(Deserialize)]
+struct HeaderVersionProbe {
+    version: u32,
+}
+
+#[derive(Deserialize)]
+struct MapHeaderV2 {
+    name: String,
+    author: String,
+    hex_size: f32,
+    orientation: HexOrientation,
+    grid_scale: f32,
+}
+
+#[derive(Deserialize)]
+struct MapDocumentV2 {
+    header: MapHeaderV2,
+    layers: Vec<LayerData>,
+    #[serde(default)]
+    terrain: Vec<(Hex, Terrain)>,
+    #[serde(default)]
+    elevation: Vec<(Hex, i32)>,
+    #[serde(default)]
+    edges: Vec<(Edge, EdgeFeature)>,
+}
+
+enum Versioned {
+    V0(Vec<(Hex, Tile)>),
+    V1(Vec<LayerData>),
+    V2(MapDocumentV2),
+    V3(MapDocument),
+}
+
+impl Versioned {
+    fn parse(data: &str) -> Result<Self> {
+        if let Ok(probe) = ron::from_str::<VersionProbe>(data) {
+            return match probe.header.version {
+                2 => Ok(Self::V2(ron::from_str(data)?)),
+                3 => Ok(Self::V3(ron::from_str(data)?)),
+                v => bail!(
+                    "Unsupported map format version {v} (this build supports up to {FORMAT_VERSION})"
+                ),
+            };
+        }
+        if let Ok(layers) = ron::from_str::<Vec<LayerData>>(data) {
+            return Ok(Self::V1(layers));
+        }
+        Ok(Self::V0(ron::from_str(data)?))
+    }
+
+    fn upgrade(self) -> Self {
+        match self {
+            Self::V0(tiles) => {
+                let mut layers = Vec::from_iter(Map::default_layers().iter().map(LayerData::from));
+                layers[0].tiles = tiles;
+                Self::V1(layers)
+            }
+            Self::V1(layers) => {
+                let header = MapHeader::default();
+                Self::V2(MapDocumentV2 {
+                    header: MapHeaderV2 {
+                        name: header.name,
+                        author: header.author,
+                        hex_size: header.cell_size,
+                        orientation: HexOrientation::Pointy,
+                        grid_scale: header.grid_scale,
+                    },
+                    layers,
+                    terrain: Vec::new(),
+                    elevation: Vec::new(),
+                    edges: Vec::new(),
+                })
+            }
+            Self::V2(document) => Self::V3(MapDocument {
+                header: MapHeader {
+                    version: 3,
+                    name: document.header.name,
+                    author: document.header.author,
+                    grid: match document.header.orientation {
+                        HexOrientation::Pointy => GridKind::PointyHex,
+                        HexOrientation::Flat => GridKind::FlatHex,
+                    },
+                    cell_size: document.header.hex_size,
+                    origin: (0., 0.),
+                    grid_scale: document.header.grid_scale,
+                    generations: Vec::new(),
+                },
+                layers: document.layers,
+                terrain: document.terrain,
+                elevation: document.elevation,
+                edges: document.edges,
+                lights: Vec::new(),
+                background: None,
+            }),
+            Self::V3(document) => Self::V3(document),
+        }
+    }
+}
+
+pub fn sorted_cells<T: Copy>(cells: &Chunks<T>) -> Vec<(Hex, T)> {
+    let mut cells = Vec::from_iter(cells.iter().map(|(hex, cell)| (*hex, *cell)));
+    cells.sort_by(|a, b| match a.0.x.cmp(&b.0.x) {
+        Ordering::Equal => a.0.y.cmp(&b.0.y),
+        o => o,
+    });
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TILES: &str = "[((x: 1, y: 2), Half(rotation: 3)), ((x: 0, y: 0), Empty)]";
+
+    fn layers(document: &MapDocument) -> Vec<(&str, usize)> {
+        Vec::from_iter(
+            document
+                .layers
+                .iter()
+                .map(|layer| (layer.name.as_str(), layer.tiles.len())),
+        )
+    }
+
+    #[test]
+    fn v0_tiles_go_to_the_floor_layer() {
+        let document = MapDocument::from_ron(TILES).unwrap();
+        assert_eq!(document.header.version, FORMAT_VERSION);
+        assert_eq!(document.header.grid, GridKind::PointyHex);
+        assert_eq!(document.header.cell_size, 32.);
+        assert_eq!(
+            layers(&document),
+            [("floor", 2), ("walls", 0), ("decoration", 0), ("gm", 0)]
+        );
+        assert!(document.layers[3].gm_only);
+        assert_eq!(
+            document.layers[0].tiles[0],
+            (Hex::new(1, 2), Tile::Half { rotation: 3 })
+        );
+    }
+
+    #[test]
+    fn v1_layers_get_the_default_header() {
+        let data = format!(
+            "[(name: \"ground\", visible: false, opacity: 0.5, gm_only: false, tiles: {TILES})]"
+        );
+        let document = MapDocument::from_ron(&data).unwrap();
+        assert_eq!(document.header.version, FORMAT_VERSION);
+        assert_eq!(document.header.grid, GridKind::PointyHex);
+        assert_eq!(document.header.grid_scale, 5.);
+        assert_eq!(layers(&document), [("ground", 2)]);
+        assert!(!document.layers[0].visible);
+        assert_eq!(document.layers[0].opacity, 0.5);
+    }
+
+    #[test]
+    fn v2_orientation_becomes_the_grid_kind() {
+        let data = format!(
+            "(header: (version: 2, name: \"Crypt\", author: \"GM\", hex_size: 40.0, \
+             orientation: Flat, grid_scale: 10.0), \
+             layers: [(name: \"floor\", visible: true, opacity: 1.0, gm_only: false, tiles: {TILES})], \
+             terrain: [((x: 0, y: 0), Difficult)])"
+        );
+        let document = MapDocument::from_ron(&data).unwrap();
+        let header = &document.header;
+        assert_eq!(header.version, FORMAT_VERSION);
+        assert_eq!(
+            (header.name.as_str(), header.author.as_str()),
+            ("Crypt", "GM")
+        );
+        assert_eq!(header.grid, GridKind::FlatHex);
+        assert_eq!((header.cell_size, header.grid_scale), (40., 10.));
+        assert_eq!(header.origin, (0., 0.));
+        assert_eq!(layers(&document), [("floor", 2)]);
+        assert_eq!(document.terrain, [(Hex::ZERO, Terrain::Difficult)]);
+    }
+
+    #[test]
+    fn v3_round_trips() {
+        let data = format!(
+            "(header: (version: 3, name: \"Keep\", author: \"\", grid: Square, cell_size: 50.0, \
+             origin: (10.0, 20.0), grid_scale: 5.0), \
+             layers: [(name: \"floor\", visible: true, opacity: 1.0, gm_only: false, tiles: {TILES})])"
+        );
+        let document = MapDocument::from_ron(&data).unwrap();
+        assert_eq!(document.header.grid, GridKind::Square);
+        assert_eq!(document.header.origin, (10., 20.));
+        assert_eq!(layers(&document), [("floor", 2)]);
+        let again = MapDocument::from_ron(&document.to_ron().unwrap()).unwrap();
+        assert_eq!(again.header.name, "Keep");
+        assert_eq!(again.layers[0].tiles, document.layers[0].tiles);
+    }
+
+    #[test]
+    fn newer_versions_are_refused() {
+        let data = "(header: (version: 4), layers: [])";
+        assert!(MapDocument::from_ron(data).is_err());
+    }
+}
@@ -0,0 +1,150 @@
+use hexx::Hex;
+use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::game::map::grid::Grid;
+
+/// Side shared by two neighbouring cells.
+///
+/// `direction` indexes `Grid::neighbors`, and is always kept in the first half
+/// of them so both cells name the same edge the same way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Edge {
+    pub hex: Hex,
+    pub direction: u8,
+}
+
+impl Edge {
+    pub fn new(grid: &Grid, hex: Hex, direction: u8) -> Self {
+        let sides = grid.sides() as u8;
+        let direction = direction % sides;
+        if direction < sides / 2 {
+            Self { hex, direction }
+        } else {
+            Self {
+                hex: grid.neighbor(hex, direction as usize),
+                direction: direction - sides / 2,
+            }
+        }
+    }
+
+    pub fn hexes(&self, grid: &Grid) -> (Hex, Hex) {
+        (self.hex, grid.neighbor(self.hex, self.direction as usize))
+    }
+
+    /// Edge closest to a world position
+    pub fn from_world_pos(grid: &Grid, pos: Vec2) -> Self {
+        let hex = grid.world_to_cell(pos);
+        let direction = grid
+            .neighbors(hex)
+            .into_iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| {
+                let a = grid.cell_to_world(*a).distance_squared(pos);
+                let b = grid.cell_to_world(*b).distance_squared(pos);
+                a.total_cmp(&b)
+            })
+            .map(|(direction, _)| direction as u8)
+            .unwrap_or_default();
+        Self::new(grid, hex, direction)
+    }
+
+    /// World positions of the two corners of the edge
+    pub fn endpoints(&self, grid: &Grid) -> (Vec2, Vec2) {
+        grid.edge_endpoints(self.hex, self.direction as usize)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum EdgeKind {
+    Door,
+    Window,
+    SecretDoor,
+    Portcullis,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum EdgeState {
+    Open,
+    Closed,
+    Locked,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct EdgeFeature {
+    pub kind: EdgeKind,
+    pub state: EdgeState,
+}
+
+impl EdgeFeature {
+    pub fn new(kind: EdgeKind) -> Self {
+        Self {
+            kind,
+            state: EdgeState::Closed,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.state == EdgeState::Open
+    }
+
+    pub fn blocks_movement(&self) -> bool {
+        match self.kind {
+            EdgeKind::Window => true,
+            EdgeKind::Door | EdgeKind::SecretDoor | EdgeKind::Portcullis => !self.is_open(),
+        }
+    }
+
+    pub fn blocks_sight(&self) -> bool {
+        match self.kind {
+            EdgeKind::Window | EdgeKind::Portcullis => false,
+            EdgeKind::Door | EdgeKind::SecretDoor => !self.is_open(),
+        }
+    }
+
+    /// Closed secret doors are not shown to players
+    pub fn is_hidden(&self, gm: bool) -> bool {
+        !gm && self.kind == EdgeKind::SecretDoor && !self.is_open()
+    }
+
+    /// Locked features stay shut until unlocked
+    pub fn toggle_open(&mut self) {
+        self.state = match self.state {
+            EdgeState::Open => EdgeState::Closed,
+            EdgeState::Closed => EdgeState::Open,
+            EdgeState::Locked => EdgeState::Locked,
+        };
+    }
+
+    pub fn toggle_lock(&mut self) {
+        self.state = match self.state {
+            EdgeState::Open | EdgeState::Closed => EdgeState::Locked,
+            EdgeState::Locked => EdgeState::Closed,
+        };
+    }
+
+    pub fn draw(&self, from: Vec2, to: Vec2, color: Color) {
+        let thickness = match self.kind {
+            EdgeKind::Door | EdgeKind::SecretDoor => 6.,
+            EdgeKind::Window | EdgeKind::Portcullis => 3.,
+        };
+        if self.is_open() {
+            // Swung open around the first corner
+            let swung = from + (to - from).perp() * 0.8;
+            draw_line(from.x, from.y, swung.x, swung.y, thickness / 2., color);
+        } else if self.kind == EdgeKind::Portcullis {
+            let bars = 5;
+            for i in 0..=bars {
+                let p = from.lerp(to, i as f32 / bars as f32);
+                draw_circle(p.x, p.y, thickness / 2., color);
+            }
+            draw_line(from.x, from.y, to.x, to.y, 1., color);
+        } else {
+            draw_line(from.x, from.y, to.x, to.y, thickness, color);
+        }
+        if self.state == EdgeState::Locked {
+            let mid = (from + to) / 2.;
+            draw_circle(mid.x, mid.y, thickness, color);
+        }
+    }
+}
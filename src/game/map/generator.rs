@@ -0,0 +1,202 @@
+use std::collections::{HashSet, VecDeque};
+
+use hexx::Hex;
+use macroquad::rand::RandGenerator;
+use serde::{Deserialize, Serialize};
+
+use crate::game::map::grid::Grid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum GeneratorKind {
+    RoomsAndCorridors,
+    Caves,
+    RandomWalk,
+}
+
+impl GeneratorKind {
+    pub const ALL: [GeneratorKind; 3] = [
+        GeneratorKind::RoomsAndCorridors,
+        GeneratorKind::Caves,
+        GeneratorKind::RandomWalk,
+    ];
+}
+
+/// One generator run, kept in the map header so it can be reproduced
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Generation {
+    pub kind: GeneratorKind,
+    pub seed: u64,
+    pub center: Hex,
+    pub radius: u32,
+}
+
+impl Generation {
+    /// Floor cells, kept one cell inside the region so the walls fit in it too
+    pub fn floor(&self, grid: &Grid) -> Vec<Hex> {
+        let rng = RandGenerator::new();
+        rng.srand(self.seed);
+        let inner = grid.range(self.center, self.radius.saturating_sub(1));
+        let floor = match self.kind {
+            GeneratorKind::RoomsAndCorridors => rooms_and_corridors(grid, &rng, &inner, self),
+            GeneratorKind::Caves => caves(grid, &rng, &inner),
+            GeneratorKind::RandomWalk => random_walk(grid, &rng, &inner, self.center),
+        };
+        Vec::from_iter(inner.into_iter().filter(|cell| floor.contains(cell)))
+    }
+}
+
+fn pick<'a>(rng: &RandGenerator, cells: &'a [Hex]) -> &'a Hex {
+    &cells[rng.gen_range(0, cells.len())]
+}
+
+fn rooms_and_corridors(
+    grid: &Grid,
+    rng: &RandGenerator,
+    inner: &[Hex],
+    generation: &Generation,
+) -> HashSet<Hex> {
+    let region = HashSet::<Hex>::from_iter(inner.iter().copied());
+    let max_room = (generation.radius / 4).max(1);
+    let mut floor = HashSet::new();
+    let mut rooms: Vec<Hex> = Vec::new();
+    for _ in 0..generation.radius * 4 {
+        let center = *pick(rng, inner);
+        let radius = rng.gen_range(1, max_room + 1);
+        let room = grid.range(center, radius);
+        // Rooms stay inside the region and keep a wall between each other
+        let fits = room.iter().all(|cell| region.contains(cell))
+            && grid
+                .range(center, radius + 1)
+                .iter()
+                .all(|cell| !floor.contains(cell));
+        if !fits {
+            continue;
+        }
+        floor.extend(room);
+        if let Some(previous) = rooms.last() {
+            floor.extend(grid.line(*previous, center));
+        }
+        rooms.push(center);
+    }
+    if rooms.is_empty() {
+        floor.extend(grid.range(generation.center, 1));
+    }
+    floor
+}
+
+fn caves(grid: &Grid, rng: &RandGenerator, inner: &[Hex]) -> HashSet<Hex> {
+    let region = HashSet::<Hex>::from_iter(inner.iter().copied());
+    let mut floor = HashSet::<Hex>::from_iter(
+        inner
+            .iter()
+            .filter(|_| rng.gen_range(0., 1.) < 0.55)
+            .copied(),
+    );
+    for _ in 0..4 {
+        floor = HashSet::from_iter(inner.iter().copied().filter(|cell| {
+            let around = grid.touching(*cell);
+            // Cells outside of the region count as rock
+            let walls = around
+                .iter()
+                .filter(|n| !region.contains(n) || !floor.contains(n))
+                .count();
+            match (walls * 2).cmp(&around.len()) {
+                std::cmp::Ordering::Greater => false,
+                std::cmp::Ordering::Less => true,
+                std::cmp::Ordering::Equal => floor.contains(cell),
+            }
+        }));
+    }
+    largest_area(grid, inner, &floor)
+}
+
+fn random_walk(grid: &Grid, rng: &RandGenerator, inner: &[Hex], start: Hex) -> HashSet<Hex> {
+    let region = HashSet::<Hex>::from_iter(inner.iter().copied());
+    let mut floor = HashSet::from([start]);
+    let mut cell = start;
+    for _ in 0..inner.len() * 2 {
+        if floor.len() * 5 >= inner.len() * 2 {
+            break;
+        }
+        let next = grid.neighbor(cell, rng.gen_range(0, grid.sides()));
+        if region.contains(&next) {
+            cell = next;
+            floor.insert(cell);
+        }
+    }
+    floor
+}
+
+/// The biggest group of floor cells connected through their sides
+fn largest_area(grid: &Grid, inner: &[Hex], floor: &HashSet<Hex>) -> HashSet<Hex> {
+    let mut seen = HashSet::new();
+    let mut largest = HashSet::new();
+    for start in inner.iter().filter(|cell| floor.contains(cell)) {
+        if seen.contains(start) {
+            continue;
+        }
+        let mut area = HashSet::from([*start]);
+        let mut queue = VecDeque::from([*start]);
+        while let Some(cell) = queue.pop_front() {
+            for n in grid.neighbors(cell) {
+                if floor.contains(&n) && area.insert(n) {
+                    queue.push_back(n);
+                }
+            }
+        }
+        seen.extend(area.iter().copied());
+        if area.len() > largest.len() {
+            largest = area;
+        }
+    }
+    largest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::map::grid::GridKind;
+
+    #[test]
+    fn same_seed_and_parameters_give_the_same_floor() {
+        for kind in [GridKind::PointyHex, GridKind::FlatHex, GridKind::Square] {
+            let grid = Grid::new(kind, 1.);
+            for generator in GeneratorKind::ALL {
+                let generation = Generation {
+                    kind: generator,
+                    seed: 42,
+                    center: Hex::new(3, -2),
+                    radius: 12,
+                };
+                let floor = generation.floor(&grid);
+                assert!(!floor.is_empty());
+                assert_eq!(generation.floor(&grid), floor, "{kind:?} {generator:?}");
+                let other = Generation {
+                    seed: 43,
+                    ..generation
+                };
+                assert_ne!(other.floor(&grid), floor, "{kind:?} {generator:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn moved_generations_give_the_moved_floor() {
+        let grid = Grid::default();
+        let offset = Hex::new(7, 4);
+        for generator in GeneratorKind::ALL {
+            let generation = Generation {
+                kind: generator,
+                seed: 7,
+                center: Hex::ZERO,
+                radius: 10,
+            };
+            let moved = Generation {
+                center: offset,
+                ..generation
+            };
+            let floor = Vec::from_iter(generation.floor(&grid).iter().map(|hex| *hex + offset));
+            assert_eq!(moved.floor(&grid), floor, "{generator:?}");
+        }
+    }
+}
@@ -0,0 +1,565 @@
+use std::f32::consts::{FRAC_1_SQRT_2, FRAC_PI_3, FRAC_PI_4, FRAC_PI_6, TAU};
+
+use hexx::{Hex, HexLayout, HexOrientation};
+use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::game::map::{
+    h2q, q2h,
+    tile::{Tile, VertexType},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum GridKind {
+    #[default]
+    PointyHex,
+    FlatHex,
+    Square,
+}
+
+impl GridKind {
+    pub fn next(&self) -> Self {
+        match self {
+            GridKind::PointyHex => GridKind::FlatHex,
+            GridKind::FlatHex => GridKind::Square,
+            GridKind::Square => GridKind::PointyHex,
+        }
+    }
+}
+
+/// Cell geometry of a map.
+///
+/// Cells are always addressed with `Hex`; on a square grid `x` is the column
+/// and `y` the row. Neighbour `i` lies across the side between tile vertex
+/// `i - 1` and `i` (in corner steps), so tile shapes rotate with the neighbours.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Grid {
+    pub kind: GridKind,
+    /// Hex circumradius, or square side length
+    pub size: f32,
+    pub origin: Vec2,
+}
+
+impl Default for Grid {
+    fn default() -> Self {
+        Self::new(GridKind::PointyHex, 32.)
+    }
+}
+
+impl Grid {
+    const SQUARE_NEIGHBORS: [Hex; 4] = [
+        Hex::new(1, 0),
+        Hex::new(0, 1),
+        Hex::new(-1, 0),
+        Hex::new(0, -1),
+    ];
+    const SQUARE_DIAGONALS: [Hex; 4] = [
+        Hex::new(1, 1),
+        Hex::new(-1, 1),
+        Hex::new(-1, -1),
+        Hex::new(1, -1),
+    ];
+
+    pub fn new(kind: GridKind, size: f32) -> Self {
+        Self {
+            kind,
+            size,
+            origin: Vec2::ZERO,
+        }
+    }
+
+    pub fn is_hex(&self) -> bool {
+        self.kind != GridKind::Square
+    }
+
+    pub fn hex_layout(&self) -> HexLayout {
+        HexLayout {
+            orientation: match self.kind {
+                GridKind::FlatHex => HexOrientation::Flat,
+                _ => HexOrientation::Pointy,
+            },
+            origin: q2h(self.origin),
+            scale: hexx::Vec2::new(self.size, self.size),
+        }
+    }
+
+    pub fn cell_to_world(&self, cell: Hex) -> Vec2 {
+        match self.kind {
+            GridKind::PointyHex | GridKind::FlatHex => {
+                h2q(self.hex_layout().hex_to_world_pos(cell))
+            }
+            GridKind::Square => self.origin + vec2(cell.x as f32, cell.y as f32) * self.size,
+        }
+    }
+
+    pub fn world_to_cell(&self, pos: Vec2) -> Hex {
+        match self.kind {
+            GridKind::PointyHex | GridKind::FlatHex => self.hex_layout().world_pos_to_hex(q2h(pos)),
+            GridKind::Square => {
+                let p = ((pos - self.origin) / self.size).round();
+                Hex::new(p.x as i32, p.y as i32)
+            }
+        }
+    }
+
+    /// Number of sides, and of neighbours sharing one
+    pub fn sides(&self) -> usize {
+        match self.kind {
+            GridKind::PointyHex | GridKind::FlatHex => 6,
+            GridKind::Square => 4,
+        }
+    }
+
+    /// Number of distinct rotations of a tile
+    pub fn rotations(&self) -> u8 {
+        self.sides() as u8
+    }
+
+    pub fn neighbors(&self, cell: Hex) -> Vec<Hex> {
+        match self.kind {
+            GridKind::PointyHex | GridKind::FlatHex => cell.all_neighbors().to_vec(),
+            GridKind::Square => Vec::from_iter(Self::SQUARE_NEIGHBORS.map(|d| cell + d)),
+        }
+    }
+
+    pub fn neighbor(&self, cell: Hex, direction: usize) -> Hex {
+        self.neighbors(cell)[direction % self.sides()]
+    }
+
+    /// Cells touching `cell` only at a corner
+    pub fn diagonals(&self, cell: Hex) -> Vec<Hex> {
+        match self.kind {
+            GridKind::PointyHex | GridKind::FlatHex => cell.all_diagonals().to_vec(),
+            GridKind::Square => Vec::from_iter(Self::SQUARE_DIAGONALS.map(|d| cell + d)),
+        }
+    }
+
+    /// Cells sharing a side, then on squares the ones sharing only a corner
+    pub fn touching(&self, cell: Hex) -> Vec<Hex> {
+        if self.is_hex() {
+            self.neighbors(cell)
+        } else {
+            [self.neighbors(cell), self.diagonals(cell)].concat()
+        }
+    }
+
+    /// Cells within `radius` steps, diagonal steps counting as one on squares
+    pub fn range(&self, cell: Hex, radius: u32) -> Vec<Hex> {
+        match self.kind {
+            GridKind::PointyHex | GridKind::FlatHex => Vec::from_iter(cell.range(radius)),
+            GridKind::Square => {
+                let r = radius as i32;
+                Vec::from_iter((-r..=r).flat_map(|y| (-r..=r).map(move |x| cell + Hex::new(x, y))))
+            }
+        }
+    }
+
+    pub fn distance(&self, a: Hex, b: Hex) -> u32 {
+        match self.kind {
+            GridKind::PointyHex | GridKind::FlatHex => a.unsigned_distance_to(b),
+            GridKind::Square => a.x.abs_diff(b.x).max(a.y.abs_diff(b.y)),
+        }
+    }
+
+    /// Cells from `a` to `b`, each sharing a side with the previous one
+    pub fn line(&self, a: Hex, b: Hex) -> Vec<Hex> {
+        match self.kind {
+            GridKind::PointyHex | GridKind::FlatHex => Vec::from_iter(a.line_to(b)),
+            GridKind::Square => {
+                let mut cells = vec![a];
+                let mut cell = a;
+                let delta = b - a;
+                while cell != b {
+                    // Step along the axis that lags behind the straight line
+                    let done = cell - a;
+                    let step_x = done.x.abs() * delta.y.abs() <= done.y.abs() * delta.x.abs();
+                    cell = if cell.x != b.x && (step_x || cell.y == b.y) {
+                        cell + Hex::new(delta.x.signum(), 0)
+                    } else {
+                        cell + Hex::new(0, delta.y.signum())
+                    };
+                    cells.push(cell);
+                }
+                cells
+            }
+        }
+    }
+
+    /// Cells exactly `radius` steps away
+    pub fn ring(&self, cell: Hex, radius: u32) -> Vec<Hex> {
+        match self.kind {
+            GridKind::PointyHex | GridKind::FlatHex if radius > 0 => {
+                Vec::from_iter(cell.ring(radius))
+            }
+            _ => Vec::from_iter(
+                self.range(cell, radius)
+                    .into_iter()
+                    .filter(|other| self.distance(cell, *other) == radius),
+            ),
+        }
+    }
+
+    /// Cells up to `target` away from `apex`, within 30° either side of the direction to it
+    pub fn cone(&self, apex: Hex, target: Hex) -> Vec<Hex> {
+        let center = self.cell_to_world(apex);
+        let direction = self.cell_to_world(target) - center;
+        let half_angle = 30f32.to_radians() + 1e-3;
+        Vec::from_iter(
+            self.range(apex, self.distance(apex, target))
+                .into_iter()
+                .filter(|cell| {
+                    let offset = self.cell_to_world(*cell) - center;
+                    *cell == apex
+                        || direction
+                            .perp_dot(offset)
+                            .atan2(direction.dot(offset))
+                            .abs()
+                            <= half_angle
+                }),
+        )
+    }
+
+    /// Cells on the outline through the `corners` cells and inside it
+    pub fn polygon(&self, corners: &[Hex]) -> Vec<Hex> {
+        let mut cells = Vec::new();
+        for (i, corner) in corners.iter().enumerate() {
+            cells.extend(self.line(*corner, corners[(i + 1) % corners.len()]));
+        }
+        let points = Vec::from_iter(corners.iter().map(|corner| self.cell_to_world(*corner)));
+        if let Some(first) = corners.first() {
+            let radius = corners
+                .iter()
+                .map(|corner| self.distance(*first, *corner))
+                .max()
+                .unwrap_or(0);
+            cells.extend(
+                self.range(*first, radius)
+                    .into_iter()
+                    .filter(|cell| polygon_contains(&points, self.cell_to_world(*cell))),
+            );
+        }
+        cells.sort_by_key(|cell| (cell.y, cell.x));
+        cells.dedup();
+        cells
+    }
+
+    /// Radius of the circle inscribed in a cell, in world units
+    pub fn inradius(&self) -> f32 {
+        match self.kind {
+            GridKind::PointyHex | GridKind::FlatHex => self.size * 3f32.sqrt() / 2.,
+            GridKind::Square => self.size / 2.,
+        }
+    }
+
+    /// Radius of the circle around a cell, in world units
+    pub fn circumradius(&self) -> f32 {
+        match self.kind {
+            GridKind::PointyHex | GridKind::FlatHex => self.size,
+            GridKind::Square => self.size * FRAC_1_SQRT_2,
+        }
+    }
+
+    /// Outline of a cell, scaled by `size`.
+    ///
+    /// Hexes use their 6 corners. Squares use 8 points alternating between
+    /// side midpoints and corners, so walls can cut a corner or half a square.
+    pub fn vertecies(&self) -> Vec<Vec2> {
+        match self.kind {
+            GridKind::PointyHex => {
+                Vec::from_iter((0..6).map(|i| Vec2::from_angle(i as f32 * FRAC_PI_3 + FRAC_PI_6)))
+            }
+            GridKind::FlatHex => {
+                Vec::from_iter((0..6).map(|i| Vec2::from_angle((i + 1) as f32 * FRAC_PI_3)))
+            }
+            GridKind::Square => Vec::from_iter((0..8).map(|i| {
+                let radius = if i % 2 == 0 { 0.5 } else { FRAC_1_SQRT_2 };
+                Vec2::from_angle(i as f32 * FRAC_PI_4) * radius
+            })),
+        }
+    }
+
+    /// Which part of the cell outline is wall for a tile
+    pub fn tile_split(&self, tile: Tile) -> Vec<VertexType> {
+        let n = self.vertecies().len();
+        // Vertex steps per rotation, and the extent of each wall shape
+        let (step, small, half) = match self.kind {
+            GridKind::PointyHex | GridKind::FlatHex => (1, 2, 3),
+            GridKind::Square => (2, 2, 4),
+        };
+        let mut split = vec![VertexType::Empty; n];
+        match tile {
+            Tile::Mask { full, both } => {
+                for (i, vertex_type) in split.iter_mut().enumerate() {
+                    if full & 1 << i != 0 {
+                        *vertex_type = VertexType::Full;
+                    } else if both & 1 << i != 0 {
+                        *vertex_type = VertexType::Both;
+                    }
+                }
+            }
+            Tile::Empty => (),
+            Tile::Full => split.fill(VertexType::Full),
+            Tile::Small { rotation } => {
+                let o = rotation as usize * step;
+                for i in 0..=small {
+                    split[(o + i) % n] = VertexType::Full;
+                }
+                split[o % n] = VertexType::Both;
+                split[(o + small) % n] = VertexType::Both;
+            }
+            Tile::Half { rotation } => {
+                let o = rotation as usize * step;
+                for i in 0..=half {
+                    split[(o + i) % n] = VertexType::Full;
+                }
+                split[o % n] = VertexType::Both;
+                split[(o + half) % n] = VertexType::Both;
+            }
+            Tile::Large { rotation } => {
+                let o = rotation as usize * step;
+                split.fill(VertexType::Full);
+                for i in 1..small {
+                    split[(o + i) % n] = VertexType::Empty;
+                }
+                split[o % n] = VertexType::Both;
+                split[(o + small) % n] = VertexType::Both;
+            }
+        }
+        split
+    }
+
+    /// The preset tile with this split, or a `Tile::Mask` of it
+    pub fn tile_from_split(&self, split: &[VertexType]) -> Tile {
+        Tile::all(self.rotations())
+            .into_iter()
+            .find(|tile| self.tile_split(*tile) == split)
+            .unwrap_or_else(|| Tile::from_mask(split))
+    }
+
+    /// Every tile that can be drawn on this grid: the presets first, then the valid masks
+    /// not matching one, leaving out those whose floor or a wall has no area
+    pub fn all_tiles(&self) -> Vec<Tile> {
+        let vertecies = self.vertecies();
+        let n = vertecies.len();
+        let presets = Tile::all(self.rotations());
+        let preset_splits = Vec::from_iter(presets.iter().map(|tile| self.tile_split(*tile)));
+        let mut tiles = presets;
+        for code in 0..3usize.pow(n as u32) {
+            let split = Vec::from_iter((0..n).map(|i| match code / 3usize.pow(i as u32) % 3 {
+                0 => VertexType::Empty,
+                1 => VertexType::Both,
+                _ => VertexType::Full,
+            }));
+            if !Tile::is_valid_split(&split) || preset_splits.contains(&split) {
+                continue;
+            }
+            let (empty, walls) = Tile::split_to_tile_variant(&split, &vertecies);
+            if std::iter::once(&empty)
+                .chain(walls.iter())
+                .all(|polygon| polygon_area(polygon) > 1e-3)
+            {
+                tiles.push(Tile::from_mask(&split));
+            }
+        }
+        tiles
+    }
+
+    /// `cell` turned around `pivot` by `steps` sides, clockwise on screen
+    pub fn rotate_cell(&self, cell: Hex, pivot: Hex, steps: i32) -> Hex {
+        let center = self.cell_to_world(pivot);
+        let angle = steps as f32 * TAU / self.sides() as f32;
+        let offset = Vec2::from_angle(angle).rotate(self.cell_to_world(cell) - center);
+        self.world_to_cell(center + offset)
+    }
+
+    /// `cell` mirrored left to right across the center of `pivot`
+    pub fn mirror_cell(&self, cell: Hex, pivot: Hex) -> Hex {
+        let center = self.cell_to_world(pivot);
+        let pos = self.cell_to_world(cell);
+        self.world_to_cell(vec2(2. * center.x - pos.x, pos.y))
+    }
+
+    /// `tile` turned by `steps` sides, matching `rotate_cell`
+    pub fn rotate_tile(&self, tile: Tile, steps: i32) -> Tile {
+        let split = self.tile_split(tile);
+        let n = split.len();
+        let shift = (steps * (n / self.sides()) as i32).rem_euclid(n as i32) as usize;
+        let mut rotated = split.clone();
+        for (i, vertex_type) in split.into_iter().enumerate() {
+            rotated[(i + shift) % n] = vertex_type;
+        }
+        self.tile_from_split(&rotated)
+    }
+
+    /// `tile` mirrored left to right, matching `mirror_cell`
+    pub fn mirror_tile(&self, tile: Tile) -> Tile {
+        let vertecies = self.vertecies();
+        let split = self.tile_split(tile);
+        let mut mirrored = split.clone();
+        for (v, vertex_type) in vertecies.iter().zip(split) {
+            let flipped = vec2(-v.x, v.y);
+            if let Some(j) = (0..vertecies.len()).min_by(|a, b| {
+                let a = vertecies[*a].distance_squared(flipped);
+                let b = vertecies[*b].distance_squared(flipped);
+                a.total_cmp(&b)
+            }) {
+                mirrored[j] = vertex_type;
+            }
+        }
+        self.tile_from_split(&mirrored)
+    }
+
+    /// Whether the floor of `tile` reaches each of the `touching` cells.
+    ///
+    /// A side is open when none of its vertices is wall, a square corner when its vertex is floor.
+    pub fn tile_openings(&self, tile: Tile) -> Vec<bool> {
+        let vertecies = self.vertecies();
+        let split = self.tile_split(tile);
+        let center = self.cell_to_world(Hex::ZERO);
+        let side = self.inradius() / self.size - 1e-3;
+        Vec::from_iter(
+            self.touching(Hex::ZERO)
+                .iter()
+                .enumerate()
+                .map(|(i, cell)| {
+                    let direction = (self.cell_to_world(*cell) - center).normalize();
+                    let mut on_side = vertecies.iter().zip(split.iter()).filter(|(v, _)| {
+                        if i < self.sides() {
+                            v.dot(direction) > side
+                        } else {
+                            v.normalize().dot(direction) > 1. - 1e-3
+                        }
+                    });
+                    if i < self.sides() {
+                        on_side.all(|(_, vertex_type)| *vertex_type != VertexType::Full)
+                    } else {
+                        on_side.all(|(_, vertex_type)| *vertex_type == VertexType::Empty)
+                    }
+                }),
+        )
+    }
+
+    /// World positions of the corners shared by `cell` and its neighbour in `direction`
+    pub fn edge_endpoints(&self, cell: Hex, direction: usize) -> (Vec2, Vec2) {
+        let a = self.cell_to_world(cell);
+        let b = self.cell_to_world(self.neighbor(cell, direction));
+        let mid = (a + b) / 2.;
+        // Both hex and square sides are `size` long
+        let half = (b - a).perp().normalize_or_zero() * self.size / 2.;
+        (mid - half, mid + half)
+    }
+}
+
+/// Area of a polygon, by the shoelace formula
+pub fn polygon_area(polygon: &[Vec2]) -> f32 {
+    let doubled: f32 = polygon
+        .iter()
+        .enumerate()
+        .map(|(i, a)| a.perp_dot(polygon[(i + 1) % polygon.len()]))
+        .sum();
+    doubled.abs() / 2.
+}
+
+/// Even-odd test of a point against a polygon
+pub fn polygon_contains(polygon: &[Vec2], point: Vec2) -> bool {
+    let mut inside = false;
+    for (i, a) in polygon.iter().enumerate() {
+        let b = polygon[(i + 1) % polygon.len()];
+        if (a.y > point.y) != (b.y > point.y)
+            && point.x < a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x)
+        {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: [GridKind; 3] = [GridKind::PointyHex, GridKind::FlatHex, GridKind::Square];
+
+    #[test]
+    fn all_tiles_count_every_distinct_shape_once() {
+        for (kind, count) in KINDS.into_iter().zip([43, 43, 125]) {
+            assert_eq!(Grid::new(kind, 1.).all_tiles().len(), count, "{kind:?}");
+        }
+    }
+
+    #[test]
+    fn neighbors_keep_their_order() {
+        let hex = [(1, 0), (0, 1), (-1, 1), (-1, 0), (0, -1), (1, -1)];
+        let square = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+        for (kind, expected) in KINDS.into_iter().zip([&hex[..], &hex, &square]) {
+            let expected = Vec::from_iter(expected.iter().map(|(x, y)| Hex::new(*x, *y)));
+            assert_eq!(
+                Grid::new(kind, 1.).neighbors(Hex::ZERO),
+                expected,
+                "{kind:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn neighbors_lie_across_the_side_before_their_vertex() {
+        for kind in KINDS {
+            let grid = Grid::new(kind, 1.);
+            let vertecies = grid.vertecies();
+            let n = vertecies.len();
+            let step = n / grid.sides();
+            for (i, neighbor) in grid.neighbors(Hex::ZERO).iter().enumerate() {
+                let direction = grid.cell_to_world(*neighbor).normalize();
+                let on_side = Vec::from_iter(
+                    (0..n).filter(|j| vertecies[*j].dot(direction) > grid.inradius() - 1e-3),
+                );
+                // Corner `k` is vertex `k * step + step - 1`; squares also have the side midpoint
+                let mut expected =
+                    Vec::from_iter((i * step..=i * step + step).map(|j| (j + n - 1) % n));
+                expected.sort();
+                assert_eq!(on_side, expected, "{kind:?} neighbor {i}");
+            }
+        }
+    }
+
+    #[test]
+    fn tile_openings_follow_the_tile_shape() {
+        for kind in KINDS {
+            let grid = Grid::new(kind, 1.);
+            let touching = grid.touching(Hex::ZERO).len();
+            assert_eq!(grid.tile_openings(Tile::Empty), vec![true; touching]);
+            assert_eq!(grid.tile_openings(Tile::Full), vec![false; touching]);
+        }
+
+        let hex = Grid::new(GridKind::PointyHex, 1.);
+        assert_eq!(
+            hex.tile_openings(Tile::Half { rotation: 0 }),
+            [true, false, false, false, true, true]
+        );
+        assert_eq!(
+            hex.tile_openings(Tile::Large { rotation: 0 }),
+            [false, true, true, false, false, false]
+        );
+        let square = Grid::new(GridKind::Square, 1.);
+        // Sides first, then the corners
+        assert_eq!(
+            square.tile_openings(Tile::Half { rotation: 0 }),
+            [false, false, false, true, false, false, true, true]
+        );
+    }
+
+    #[test]
+    fn tile_openings_turn_with_the_tile() {
+        for kind in KINDS {
+            let grid = Grid::new(kind, 1.);
+            let sides = grid.sides();
+            for tile in grid.all_tiles() {
+                let openings = grid.tile_openings(tile);
+                let turned = grid.tile_openings(grid.rotate_tile(tile, 1));
+                for i in 0..sides {
+                    assert_eq!(turned[(i + 1) % sides], openings[i], "{kind:?} {tile:?}");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use crate::game::map::{chunks::Chunks, mesh::ChunkMeshes, tile::Tile};
+
+#[derive(Debug, Clone)]
+pub struct Layer {
+    pub name: String,
+    pub tiles: Chunks<Tile>,
+    pub visible: bool,
+    pub opacity: f32,
+    pub gm_only: bool,
+    pub meshes: ChunkMeshes,
+}
+
+impl Layer {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            tiles: Chunks::new(),
+            visible: true,
+            opacity: 1.,
+            gm_only: false,
+            meshes: ChunkMeshes::default(),
+        }
+    }
+
+    pub fn new_gm_only(name: &str) -> Self {
+        Self {
+            gm_only: true,
+            ..Self::new(name)
+        }
+    }
+
+    /// Whether the layer should be drawn for a GM (`gm == true`) or a player.
+    pub fn is_shown(&self, gm: bool) -> bool {
+        self.visible && (gm || !self.gm_only)
+    }
+
+    pub fn change_opacity(&mut self, delta: f32) {
+        self.opacity = (self.opacity + delta).clamp(0., 1.);
+    }
+}
@@ -0,0 +1,28 @@
+use hexx::Hex;
+use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Light placed on a cell
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LightSource {
+    pub hex: Hex,
+    /// Radius in cells
+    pub range: f32,
+    pub intensity: f32,
+    /// 0xRRGGBB
+    pub color: u32,
+}
+
+impl LightSource {
+    pub fn draw(&self, pos: Vec2, cell_width: f32) {
+        let color = Color::from_hex(self.color);
+        let radius = self.range * cell_width;
+        draw_circle(
+            pos.x,
+            pos.y,
+            radius,
+            color.with_alpha(0.15 * self.intensity.clamp(0., 1.)),
+        );
+        draw_circle(pos.x, pos.y, cell_width / 8., color);
+    }
+}
@@ -0,0 +1,197 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt,
+};
+
+use hexx::Hex;
+
+use crate::game::map::{Map, chunks::Chunks, tile::Tile, walls::mismatch};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LintKind {
+    /// Floor open towards a cell without any tile
+    FloorTouchesVoid,
+    /// A wall that fits the cells around it better turned another way
+    WrongWallRotation,
+    /// Floor that cannot be walked to from the biggest floor area
+    UnreachablePocket,
+}
+
+impl LintKind {
+    pub fn severity(&self) -> Severity {
+        match self {
+            LintKind::FloorTouchesVoid | LintKind::WrongWallRotation => Severity::Error,
+            // Secret rooms are left unconnected on purpose
+            LintKind::UnreachablePocket => Severity::Warning,
+        }
+    }
+}
+
+/// A problem found on the cells of the floor layer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    pub kind: LintKind,
+    pub hexes: Vec<Hex>,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.kind.severity() {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        let hex = self.hexes.first().copied().unwrap_or_default();
+        write!(f, "{severity}: {:?} at ({}, {})", self.kind, hex.x, hex.y)?;
+        if self.hexes.len() > 1 {
+            write!(f, ", {} cells", self.hexes.len())?;
+        }
+        Ok(())
+    }
+}
+
+impl Map {
+    /// Issues of the floor layer, errors first, then by position.
+    ///
+    /// The other layers are drawn over the floor and never walked on, so they are left out.
+    pub fn lint(&self) -> Vec<Issue> {
+        let mut issues = match self.layers.get(Map::FLOOR_LAYER) {
+            Some(layer) => self.lint_tiles(&layer.tiles),
+            None => Vec::new(),
+        };
+        issues.sort_by_key(|issue| {
+            let hex = issue.hexes.first().copied().unwrap_or_default();
+            (std::cmp::Reverse(issue.kind.severity()), hex.y, hex.x)
+        });
+        issues
+    }
+
+    fn lint_tiles(&self, tiles: &Chunks<Tile>) -> Vec<Issue> {
+        let grid = &self.grid;
+        let mut openings = HashMap::new();
+        let mut opens = |tile: Tile| {
+            openings
+                .entry(tile)
+                .or_insert_with(|| grid.tile_openings(tile))
+                .clone()
+        };
+        let issue = |kind, hexes| Issue { kind, hexes };
+        let mut issues = Vec::new();
+        // Cells each cell's floor reaches, where the floor of that cell reaches back
+        let mut links: HashMap<Hex, Vec<Hex>> = HashMap::new();
+        for (hex, tile) in tiles.iter() {
+            let touching = grid.touching(*hex);
+            let open = opens(*tile);
+            let floor = Vec::from_iter(touching.iter().map(|n| {
+                tiles.get(n).is_some_and(|other| {
+                    let back = grid.touching(*n).iter().position(|m| m == hex);
+                    back.is_some_and(|back| opens(*other)[back])
+                })
+            }));
+            let cell_links = Vec::from_iter(
+                touching
+                    .iter()
+                    .zip(open.iter().zip(floor.iter()))
+                    .filter(|(_, (open, floor))| **open && **floor)
+                    .map(|(n, _)| *n),
+            );
+            links.insert(*hex, cell_links);
+
+            // Walls away from any floor are left as drawn, as pillars or decoration
+            let borders_floor = touching.iter().any(|n| tiles.get(n) == Some(&Tile::Empty));
+            if *tile != Tile::Empty && !borders_floor {
+                continue;
+            }
+            if !tile.is_empty_or_full() {
+                let cost = mismatch(&open, &floor);
+                let turned_better = (1..grid.sides() as i32)
+                    .map(|steps| grid.rotate_tile(*tile, steps))
+                    .any(|turned| mismatch(&opens(turned), &floor) < cost);
+                if turned_better {
+                    issues.push(issue(LintKind::WrongWallRotation, vec![*hex]));
+                    continue;
+                }
+            }
+            let void = touching
+                .iter()
+                .zip(open)
+                .any(|(n, open)| open && !tiles.contains_key(n));
+            if void {
+                issues.push(issue(LintKind::FloorTouchesVoid, vec![*hex]));
+            }
+        }
+
+        let mut areas: Vec<Vec<Hex>> = Vec::new();
+        let mut seen = HashSet::new();
+        let mut starts = Vec::from_iter(
+            tiles
+                .iter()
+                .filter(|(_, tile)| **tile == Tile::Empty)
+                .map(|(hex, _)| *hex),
+        );
+        starts.sort_by_key(|hex| (hex.y, hex.x));
+        for start in starts {
+            if !seen.insert(start) {
+                continue;
+            }
+            let mut area = vec![start];
+            let mut queue = VecDeque::from([start]);
+            while let Some(hex) = queue.pop_front() {
+                for n in links.get(&hex).into_iter().flatten() {
+                    if seen.insert(*n) {
+                        area.push(*n);
+                        queue.push_back(*n);
+                    }
+                }
+            }
+            areas.push(area);
+        }
+        let largest = areas
+            .iter()
+            .enumerate()
+            .max_by_key(|(i, area)| (area.len(), std::cmp::Reverse(*i)))
+            .map(|(i, _)| i);
+        for (i, mut area) in areas.into_iter().enumerate() {
+            if Some(i) != largest {
+                area.sort_by_key(|hex| (hex.y, hex.x));
+                issues.push(issue(LintKind::UnreachablePocket, area));
+            }
+        }
+        issues
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_map_has_no_errors() {
+        let map = Map::from_file("assets/map/001.ron").unwrap();
+        let errors = Vec::from_iter(
+            map.lint()
+                .into_iter()
+                .filter(|issue| issue.kind.severity() == Severity::Error)
+                .map(|issue| issue.to_string()),
+        );
+        assert!(errors.is_empty(), "{errors:#?}");
+    }
+
+    #[test]
+    fn floor_open_to_void_is_an_error() {
+        let mut map = Map::default();
+        map.build_tile_variants();
+        map.layers[Map::FLOOR_LAYER]
+            .tiles
+            .insert(Hex::ZERO, Tile::Empty);
+        let issues = map.lint();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].kind, LintKind::FloorTouchesVoid);
+        assert_eq!(issues[0].hexes, vec![Hex::ZERO]);
+    }
+}
@@ -1,26 +1,84 @@
 use std::{
-    cmp::Ordering,
     collections::HashMap,
     fs,
     io::{BufWriter, Write},
+    path::Path,
 };
 
-use crate::game::map::{Map, tile::Tile};
+use crate::game::map::{
+    Map,
+    background::{Background, BackgroundData},
+    chunks::Chunks,
+    document::{FORMAT_VERSION, LayerData, MapDocument, MapHeader, sorted_cells},
+    layer::Layer,
+    tiled::{self, TiledMap, TiledMapping},
+    uvtt::{self, UvttMap},
+};
 
 use anyhow::Result;
-use hexx::Hex;
 use macroquad::prelude::*;
 
 impl Map {
     pub async fn load_map(&mut self) -> Result<()> {
-        self.tiles.clear();
-        let data = load_string(&self.current_map_file).await?;
-        let tiles = ron::from_str::<Vec<(Hex, Tile)>>(&data)?;
-        self.tiles = HashMap::from_iter(tiles);
+        let path = self.current_map_file.clone();
+        let data = load_string(&path).await?;
+        #[cfg_attr(target_arch = "wasm32", allow(unused_variables))]
+        let uvtt = self.load_data(&data)?;
+        // Opening an import in the app is what writes its image, reading it elsewhere does not
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(uvtt) = uvtt
+            && let Some(background) = &mut self.background
+        {
+            write_image(background, &uvtt.image_bytes()?)?;
+        }
+        if let Some(background) = &mut self.background {
+            background.load_texture().await;
+        }
         Ok(())
     }
 
+    /// Reads a map or importable file from disk, outside of the one being edited
+    pub fn from_file(path: &str) -> Result<Map> {
+        let mut map = Map::default();
+        map.build_tile_variants();
+        map.current_map_file = path.to_string();
+        map.load_data(&fs::read_to_string(path)?)?;
+        Ok(map)
+    }
+
+    /// Loads the contents of `current_map_file`, importing it when it is not a map document.
+    /// Returns the Universal VTT file read, whose image is not written by the import.
+    pub fn load_data(&mut self, data: &str) -> Result<Option<UvttMap>> {
+        let path = Path::new(&self.current_map_file).to_path_buf();
+        let mut imported = None;
+        if uvtt::is_uvtt_file(&self.current_map_file) {
+            let image = path.with_extension("png").to_string_lossy().to_string();
+            let uvtt = UvttMap::from_json(data)?;
+            self.import_uvtt(&uvtt, &image)?;
+            imported = Some(uvtt);
+        } else if tiled::is_tiled_file(&self.current_map_file) {
+            let mapping = fs::read_to_string(TiledMapping::path_for(&self.current_map_file))?;
+            self.import_tiled(&TiledMap::parse(data)?, &TiledMapping::from_ron(&mapping)?);
+        } else {
+            self.apply_document(MapDocument::from_ron(data)?);
+            return Ok(None);
+        }
+        self.name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        // Saving goes next to the import instead of over it
+        self.current_map_file = path.with_extension("ron").to_string_lossy().to_string();
+        Ok(imported)
+    }
+
+    /// Writes the map to `current_map_file`, unless there is none as loading it failed
     pub async fn save_map(&self) -> Result<()> {
+        if self.current_map_file.is_empty() {
+            warn!("Not saving the map, its file failed to load");
+            return Ok(());
+        }
+
         #[cfg(target_arch = "wasm32")]
         {}
 
@@ -33,19 +91,119 @@ impl Map {
                     .write(true)
                     .open(&self.current_map_file)?,
             );
-            let mut tiles = Vec::from_iter(self.tiles.iter());
-            tiles.sort_by(|a, b| match a.0.x.cmp(&b.0.x) {
-                Ordering::Equal => a.0.y.cmp(&b.0.y),
-                o => o,
-            });
-
-            let data = ron::ser::to_string_pretty(
-                &tiles,
-                ron::ser::PrettyConfig::default().compact_structs(true),
-            )?;
+            let data = self.to_document().to_ron()?;
             file.write_all(data.as_bytes())?;
         }
 
         Ok(())
     }
+
+    pub fn to_document(&self) -> MapDocument {
+        MapDocument {
+            header: MapHeader {
+                version: FORMAT_VERSION,
+                name: self.name.clone(),
+                author: self.author.clone(),
+                grid: self.grid.kind,
+                cell_size: self.grid.size,
+                origin: self.grid.origin.into(),
+                grid_scale: self.grid_scale,
+                generations: self.generations.clone(),
+            },
+            layers: Vec::from_iter(self.layers.iter().map(LayerData::from)),
+            terrain: sorted_cells(&self.terrain),
+            elevation: sorted_cells(&self.elevation),
+            edges: {
+                let mut edges = Vec::from_iter(self.edges.iter().map(|(e, f)| (*e, *f)));
+                edges.sort_by_key(|(edge, _)| (edge.hex.x, edge.hex.y, edge.direction));
+                edges
+            },
+            lights: self.lights.clone(),
+            background: self.background.as_ref().map(BackgroundData::from),
+        }
+    }
+
+    pub fn apply_document(&mut self, document: MapDocument) {
+        let MapDocument {
+            header,
+            layers,
+            terrain,
+            elevation,
+            edges,
+            lights,
+            background,
+        } = document;
+        self.name = header.name;
+        self.author = header.author;
+        self.grid_scale = header.grid_scale;
+        self.generations = header.generations;
+        self.grid.origin = header.origin.into();
+        self.set_grid(header.grid, header.cell_size);
+        self.layers = Vec::from_iter(layers.into_iter().map(Layer::from));
+        if self.layers.is_empty() {
+            self.layers = Self::default_layers();
+        }
+        self.active_layer = 0;
+        self.terrain = Chunks::from_iter(terrain);
+        self.elevation = Chunks::from_iter(elevation);
+        self.edges = HashMap::from_iter(edges);
+        self.lights = lights;
+        self.background = background.map(Background::from);
+    }
+}
+
+/// Writes the image of `background`, under a free name when another image already has its own
+#[cfg(not(target_arch = "wasm32"))]
+fn write_image(background: &mut Background, bytes: &[u8]) -> Result<()> {
+    let wanted = Path::new(&background.image).to_path_buf();
+    let stem = wanted
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let mut image = wanted.clone();
+    let mut n = 0;
+    // The same image written when the import was last opened is kept as is
+    while image.exists() && fs::read(&image)? != bytes {
+        n += 1;
+        image = wanted.with_file_name(format!("{stem}-{n}.png"));
+    }
+    if n > 0 {
+        warn!(
+            "{} already exists, writing the imported background to {}",
+            wanted.display(),
+            image.display()
+        );
+        background.image = image.to_string_lossy().to_string();
+    }
+    if !image.exists() {
+        fs::write(&image, bytes)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn imported_images_never_replace_another_image() {
+        let dir = std::env::temp_dir().join(format!("myvtt-import-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let image = dir.join("map.png");
+        fs::write(&image, b"drawn by hand").unwrap();
+
+        let mut background = Background::new(&image.to_string_lossy(), Vec2::ZERO);
+        write_image(&mut background, b"imported").unwrap();
+        assert_eq!(fs::read(&image).unwrap(), b"drawn by hand");
+        assert_eq!(Path::new(&background.image), dir.join("map-1.png"));
+        assert_eq!(fs::read(&background.image).unwrap(), b"imported");
+
+        // Opening the import again finds its own image
+        let mut background = Background::new(&image.to_string_lossy(), Vec2::ZERO);
+        write_image(&mut background, b"imported").unwrap();
+        assert_eq!(Path::new(&background.image), dir.join("map-1.png"));
+        assert!(!dir.join("map-2.png").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,298 @@
+use std::collections::HashSet;
+
+use anyhow::{Result, bail};
+use hexx::Hex;
+
+use crate::game::map::{
+    Map,
+    layer::Layer,
+    tile::{Tile, VertexType},
+    transform::MapTransform,
+};
+
+/// What a cell used by both maps ends up holding
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MergePolicy {
+    KeepOurs,
+    TakeTheirs,
+    /// Wall wherever either map has wall, everything but tiles kept as ours
+    UnionWalls,
+}
+
+impl MergePolicy {
+    pub const ALL: [MergePolicy; 3] = [
+        MergePolicy::KeepOurs,
+        MergePolicy::TakeTheirs,
+        MergePolicy::UnionWalls,
+    ];
+}
+
+/// A tile written by a merge
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MergedTile {
+    pub layer: usize,
+    pub hex: Hex,
+    pub tile: Tile,
+    /// Both maps had a different tile here
+    pub conflict: bool,
+}
+
+impl Map {
+    /// Layer of this map each layer of `other` goes to, matched by name.
+    /// Layers missing here are numbered after the existing ones.
+    fn merge_targets(&self, other: &Map) -> Vec<usize> {
+        let mut added = self.layers.len();
+        Vec::from_iter(other.layers.iter().map(|layer| {
+            match self.layers.iter().position(|ours| ours.name == layer.name) {
+                Some(i) => i,
+                None => {
+                    added += 1;
+                    added - 1
+                }
+            }
+        }))
+    }
+
+    /// The tiles merging `other` moved by `offset` results in, where they differ from ours
+    pub fn merged_tiles(&self, other: &Map, offset: Hex, policy: MergePolicy) -> Vec<MergedTile> {
+        let targets = self.merge_targets(other);
+        let mut merged = Vec::new();
+        for (layer, target) in other.layers.iter().zip(targets) {
+            for (hex, theirs) in layer.tiles.iter() {
+                let hex = *hex + offset;
+                let ours = self
+                    .layers
+                    .get(target)
+                    .and_then(|layer| layer.tiles.get(&hex));
+                let (tile, conflict) = match ours {
+                    None => (*theirs, false),
+                    Some(ours) if ours == theirs => continue,
+                    Some(ours) => match policy {
+                        MergePolicy::KeepOurs => (*ours, true),
+                        MergePolicy::TakeTheirs => (*theirs, true),
+                        MergePolicy::UnionWalls => (self.union_walls(*ours, *theirs), true),
+                    },
+                };
+                merged.push(MergedTile {
+                    layer: target,
+                    hex,
+                    tile,
+                    conflict,
+                });
+            }
+        }
+        merged.sort_by_key(|merged| (merged.layer, merged.hex.y, merged.hex.x));
+        merged
+    }
+
+    /// The tile walled wherever `a` or `b` is, or a full wall when that shape cannot be drawn
+    pub fn union_walls(&self, a: Tile, b: Tile) -> Tile {
+        let rank = |vertex_type: VertexType| match vertex_type {
+            VertexType::Empty => 0,
+            VertexType::Both => 1,
+            VertexType::Full => 2,
+        };
+        let split = Vec::from_iter(
+            self.grid
+                .tile_split(a)
+                .into_iter()
+                .zip(self.grid.tile_split(b))
+                .map(|(a, b)| if rank(a) >= rank(b) { a } else { b }),
+        );
+        let tile = self.grid.tile_from_split(&split);
+        if Tile::is_valid_split(&split) && self.tile_variants.contains_key(&tile) {
+            tile
+        } else {
+            Tile::Full
+        }
+    }
+
+    /// Merges `other` moved by `offset` into this map, adding the layers it is missing.
+    ///
+    /// Terrain, heights, edges and lights follow `policy` too, with union keeping ours.
+    /// The walls of the floor layer are fixed along the seam. Returns the number of conflicts.
+    pub fn merge(&mut self, other: &Map, offset: Hex, policy: MergePolicy) -> Result<usize> {
+        if other.grid.kind != self.grid.kind {
+            bail!(
+                "cannot merge a {:?} map into a {:?} map",
+                other.grid.kind,
+                self.grid.kind
+            );
+        }
+        let merged = self.merged_tiles(other, offset, policy);
+        for (layer, target) in other.layers.iter().zip(self.merge_targets(other)) {
+            if target >= self.layers.len() {
+                self.layers.push(Layer {
+                    tiles: Default::default(),
+                    meshes: Default::default(),
+                    ..layer.clone()
+                });
+            }
+        }
+        let floor = Map::FLOOR_LAYER;
+        let before = HashSet::<Hex>::from_iter(self.layers[floor].tiles.keys().copied());
+        for tile in merged.iter() {
+            self.layers[tile.layer].tiles.insert(tile.hex, tile.tile);
+        }
+
+        let takes_theirs = |ours: bool| !ours || policy == MergePolicy::TakeTheirs;
+        for (hex, terrain) in other.terrain.iter() {
+            let hex = *hex + offset;
+            if takes_theirs(self.terrain.contains_key(&hex)) {
+                self.set_terrain(hex, *terrain);
+            }
+        }
+        for (hex, elevation) in other.elevation.iter() {
+            let hex = *hex + offset;
+            if takes_theirs(self.elevation.contains_key(&hex)) {
+                self.set_elevation(hex, *elevation);
+            }
+        }
+        let translate = MapTransform::Translate(offset);
+        for (edge, feature) in other.edges.iter() {
+            let edge = translate.edge(&self.grid, *edge);
+            if takes_theirs(self.edges.contains_key(&edge)) {
+                self.edges.insert(edge, *feature);
+            }
+        }
+        for light in other.lights.iter() {
+            let hex = light.hex + offset;
+            let ours = self.lights.iter().position(|ours| ours.hex == hex);
+            match ours {
+                Some(i) if policy == MergePolicy::TakeTheirs => {
+                    self.lights[i] = *light;
+                    self.lights[i].hex = hex;
+                }
+                Some(_) => (),
+                None => {
+                    self.lights.push(*light);
+                    if let Some(added) = self.lights.last_mut() {
+                        added.hex = hex;
+                    }
+                }
+            }
+        }
+
+        // The seam: placed cells touching cells that were ours alone, and those cells
+        let placed = HashSet::<Hex>::from_iter(
+            merged
+                .iter()
+                .filter(|tile| tile.layer == floor)
+                .map(|tile| tile.hex),
+        );
+        let seam = Vec::from_iter(placed.iter().flat_map(|hex| {
+            let touching = self.grid.touching(*hex);
+            let ours = Vec::from_iter(
+                touching
+                    .into_iter()
+                    .filter(|n| before.contains(n) && !placed.contains(n)),
+            );
+            if ours.is_empty() {
+                ours
+            } else {
+                [vec![*hex], ours].concat()
+            }
+        }));
+        // Walls no longer touching floor are left, they may be meant as solid rock
+        for (hex, tile) in self.wall_changes(floor, seam) {
+            if let Some(tile) = tile {
+                self.layers[floor].tiles.insert(hex, tile);
+            }
+        }
+        Ok(merged.iter().filter(|tile| tile.conflict).count())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::map::chunks::Chunks;
+
+    fn floor_map(tiles: impl IntoIterator<Item = (Hex, Tile)>) -> Map {
+        let mut map = Map::default();
+        map.build_tile_variants();
+        map.layers[Map::FLOOR_LAYER].tiles = Chunks::from_iter(tiles);
+        map
+    }
+
+    fn floor_tile(map: &Map, hex: Hex) -> Option<Tile> {
+        map.layers[Map::FLOOR_LAYER].tiles.get(&hex).copied()
+    }
+
+    fn floor_tiles(map: &Map) -> Vec<(Hex, Tile)> {
+        let mut tiles = Vec::from_iter(
+            map.layers[Map::FLOOR_LAYER]
+                .tiles
+                .iter()
+                .map(|(hex, tile)| (*hex, *tile)),
+        );
+        tiles.sort_by_key(|(hex, _)| (hex.y, hex.x));
+        tiles
+    }
+
+    const A: Hex = Hex::ZERO;
+    const B: Hex = Hex::new(5, 0);
+
+    #[test]
+    fn keep_ours_keeps_our_tiles_and_counts_conflicts() {
+        let mut ours = floor_map([(A, Tile::Full)]);
+        let theirs = floor_map([(A, Tile::Empty), (B, Tile::Half { rotation: 0 })]);
+        let conflicts = ours
+            .merge(&theirs, Hex::ZERO, MergePolicy::KeepOurs)
+            .unwrap();
+        assert_eq!(conflicts, 1);
+        assert_eq!(floor_tile(&ours, A), Some(Tile::Full));
+        assert_eq!(floor_tile(&ours, B), Some(Tile::Half { rotation: 0 }));
+    }
+
+    #[test]
+    fn take_theirs_replaces_our_tiles() {
+        let mut ours = floor_map([(A, Tile::Full), (B, Tile::Empty)]);
+        let theirs = floor_map([(A, Tile::Empty), (B, Tile::Empty)]);
+        let conflicts = ours
+            .merge(&theirs, Hex::ZERO, MergePolicy::TakeTheirs)
+            .unwrap();
+        assert_eq!(conflicts, 1);
+        assert_eq!(floor_tile(&ours, A), Some(Tile::Empty));
+        assert_eq!(floor_tile(&ours, B), Some(Tile::Empty));
+    }
+
+    #[test]
+    fn union_walls_keeps_the_walls_of_both() {
+        let mut ours = floor_map([(A, Tile::Half { rotation: 0 }), (B, Tile::Empty)]);
+        let theirs = floor_map([(A, Tile::Empty), (B, Tile::Full)]);
+        let conflicts = ours
+            .merge(&theirs, Hex::ZERO, MergePolicy::UnionWalls)
+            .unwrap();
+        assert_eq!(conflicts, 2);
+        assert_eq!(floor_tile(&ours, A), Some(Tile::Half { rotation: 0 }));
+        assert_eq!(floor_tile(&ours, B), Some(Tile::Full));
+    }
+
+    #[test]
+    fn merging_onto_another_layer_walls_the_floor_seam() {
+        let room = Vec::from_iter(
+            Hex::ZERO
+                .range(1)
+                .map(|hex| (hex, Tile::Empty))
+                .chain(Hex::ZERO.ring(2).map(|hex| (hex, Tile::Full))),
+        );
+        let theirs = floor_map([(Hex::ZERO, Tile::Empty)]);
+        let offset = Hex::new(3, 0);
+
+        let mut on_floor = floor_map(room.clone());
+        on_floor
+            .merge(&theirs, offset, MergePolicy::KeepOurs)
+            .unwrap();
+        let mut on_decoration = floor_map(room);
+        on_decoration.active_layer = 2;
+        on_decoration
+            .merge(&theirs, offset, MergePolicy::KeepOurs)
+            .unwrap();
+
+        // The wall between both floors opens to each side
+        assert_ne!(floor_tile(&on_floor, Hex::new(2, 0)), Some(Tile::Full));
+        assert!(on_decoration.layers[2].tiles.is_empty());
+        assert_eq!(floor_tiles(&on_decoration), floor_tiles(&on_floor));
+    }
+}
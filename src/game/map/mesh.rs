@@ -0,0 +1,122 @@
+use std::{collections::HashMap, fmt};
+
+use hexx::Hex;
+use macroquad::prelude::*;
+
+use crate::game::map::chunks::{ChunkCoord, Chunks};
+
+/// Accumulates convex polygons into as few `Mesh`es as the draw call buffer allows
+#[derive(Default)]
+pub struct MeshBuilder {
+    meshes: Vec<Mesh>,
+}
+
+impl MeshBuilder {
+    // macroquad clamps a single draw call to 10000 vertices and 5000 indices by default
+    pub const MAX_VERTICES: usize = 8000;
+    pub const MAX_INDICES: usize = 4800;
+
+    /// Adds a triangle fan around `vertecies[0]`
+    pub fn fan(&mut self, vertecies: &[Vec2], color: Color) {
+        if vertecies.len() < 3 {
+            return;
+        }
+        let index_count = (vertecies.len() - 2) * 3;
+        let mesh = match self.meshes.last_mut() {
+            Some(mesh)
+                if mesh.vertices.len() + vertecies.len() <= Self::MAX_VERTICES
+                    && mesh.indices.len() + index_count <= Self::MAX_INDICES =>
+            {
+                mesh
+            }
+            _ => {
+                self.meshes.push(Mesh {
+                    vertices: Vec::new(),
+                    indices: Vec::new(),
+                    texture: None,
+                });
+                self.meshes.last_mut().unwrap()
+            }
+        };
+
+        let first = mesh.vertices.len() as u16;
+        mesh.vertices.extend(
+            vertecies
+                .iter()
+                .map(|v| Vertex::new(v.x, v.y, 0., 0., 0., color)),
+        );
+        for i in 1..vertecies.len() as u16 - 1 {
+            mesh.indices
+                .extend_from_slice(&[first, first + i, first + i + 1]);
+        }
+    }
+
+    pub fn circle(&mut self, pos: Vec2, radius: f32, sides: u8, color: Color) {
+        let vertecies = Vec::from_iter((0..sides).map(|i| {
+            pos + Vec2::from_angle(i as f32 / sides as f32 * std::f32::consts::TAU) * radius
+        }));
+        self.fan(&vertecies, color);
+    }
+
+    pub fn finish(self) -> Vec<Mesh> {
+        self.meshes
+    }
+}
+
+/// Per chunk meshes of a `Chunks` storage, rebuilt when their chunk gets dirty
+#[derive(Default)]
+pub struct ChunkMeshes {
+    meshes: HashMap<ChunkCoord, Vec<Mesh>>,
+    style: Vec<Color>,
+}
+
+impl Clone for ChunkMeshes {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl fmt::Debug for ChunkMeshes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChunkMeshes")
+            .field("chunks", &self.meshes.len())
+            .finish()
+    }
+}
+
+impl ChunkMeshes {
+    /// Rebuilds the meshes of dirty chunks, or all of them when `style` changed
+    pub fn update<T>(
+        &mut self,
+        cells: &mut Chunks<T>,
+        style: &[Color],
+        mut build: impl FnMut(&mut MeshBuilder, Hex, &T),
+    ) {
+        if self.style != style {
+            self.style = style.to_vec();
+            self.meshes.clear();
+            cells.mark_all_dirty();
+        }
+
+        for coord in cells.take_dirty() {
+            let Some(chunk) = cells.chunk(&coord) else {
+                self.meshes.remove(&coord);
+                continue;
+            };
+            let mut builder = MeshBuilder::default();
+            for (hex, cell) in chunk.iter() {
+                build(&mut builder, *hex, cell);
+            }
+            self.meshes.insert(coord, builder.finish());
+        }
+    }
+
+    pub fn get(&self, coord: &ChunkCoord) -> Option<&Vec<Mesh>> {
+        self.meshes.get(coord)
+    }
+
+    pub fn clear(&mut self) {
+        self.meshes.clear();
+        self.style.clear();
+    }
+}
@@ -0,0 +1,77 @@
+use std::{fs, path::Path};
+
+use anyhow::Result;
+use hexx::Hex;
+use serde::{Deserialize, Serialize};
+
+use crate::game::map::{grid::GridKind, tile::Tile};
+
+/// Where stamps are saved and browsed from
+pub const STAMP_DIR: &str = "assets/stamps";
+
+/// A reusable group of tiles, relative to the cell it is placed at
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Stamp {
+    /// File stem, rename the file to rename the stamp
+    #[serde(skip)]
+    pub name: String,
+    /// Tiles only fit the grid they were copied from
+    pub grid: GridKind,
+    pub tiles: Vec<(Hex, Tile)>,
+}
+
+impl Stamp {
+    pub fn from_ron(data: &str) -> Result<Self> {
+        Ok(ron::from_str(data)?)
+    }
+
+    pub fn to_ron(&self) -> Result<String> {
+        Ok(ron::ser::to_string_pretty(
+            self,
+            ron::ser::PrettyConfig::default().compact_structs(true),
+        )?)
+    }
+
+    /// Stamps in `dir` made on `grid`, by name. Files that do not parse are skipped.
+    pub fn load_all(dir: &str, grid: GridKind) -> Vec<Stamp> {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return Vec::new();
+        };
+        let mut stamps = Vec::from_iter(entries.filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? != "ron" {
+                return None;
+            }
+            let mut stamp = Stamp::from_ron(&fs::read_to_string(&path).ok()?).ok()?;
+            stamp.name = path.file_stem()?.to_string_lossy().to_string();
+            Some(stamp)
+        }));
+        stamps.retain(|stamp| stamp.grid == grid);
+        stamps.sort_by(|a, b| a.name.cmp(&b.name));
+        stamps
+    }
+
+    /// Writes the stamp to the first free `<name>-1.ron`, `<name>-2.ron`, … in `dir`,
+    /// naming it after the file. Characters a file name cannot hold become `_`.
+    pub fn save(&mut self, dir: &str, name: &str) -> Result<()> {
+        fs::create_dir_all(dir)?;
+        let name = String::from_iter(name.trim().chars().map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' || c == ' ' {
+                c
+            } else {
+                '_'
+            }
+        }));
+        let name = if name.is_empty() { "stamp" } else { &name };
+        let path = (1..)
+            .map(|i| Path::new(dir).join(format!("{name}-{i}.ron")))
+            .find(|path| !path.exists())
+            .unwrap_or_default();
+        fs::write(&path, self.to_ron()?)?;
+        self.name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        Ok(())
+    }
+}
@@ -0,0 +1,333 @@
+use std::{collections::HashMap, fmt::Write as _, fs};
+
+use anyhow::Result;
+use hexx::Hex;
+use macroquad::prelude::*;
+
+use crate::game::{
+    map::{Map, chunks::Chunks, grid::GridKind, tile::Tile},
+    theme::{Theme, ThemeColor},
+};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SvgOptions {
+    pub grid_lines: bool,
+    pub labels: bool,
+    /// Include gm-only layers
+    pub gm: bool,
+}
+
+impl Map {
+    pub fn export_svg(&self, path: &str, theme: &Theme, options: &SvgOptions) -> Result<()> {
+        fs::write(path, self.to_svg(theme, options))?;
+        Ok(())
+    }
+
+    /// Each shown layer becomes a group with one merged floor and one merged wall path
+    pub fn to_svg(&self, theme: &Theme, options: &SvgOptions) -> String {
+        let color = |color: ThemeColor| format!("#{:06x}", theme.u32(color));
+        let layers = Vec::from_iter(
+            self.layers
+                .iter()
+                .filter(|layer| layer.is_shown(options.gm)),
+        );
+
+        let mut min = Vec2::splat(f32::INFINITY);
+        let mut max = Vec2::splat(f32::NEG_INFINITY);
+        for hex in layers.iter().flat_map(|layer| layer.tiles.keys()) {
+            let pos = self.grid.cell_to_world(*hex);
+            min = min.min(pos - self.grid.circumradius());
+            max = max.max(pos + self.grid.circumradius());
+        }
+        if min.x > max.x {
+            (min, max) = (Vec2::ZERO, Vec2::ZERO);
+        }
+        let size = max - min;
+
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="{} {} {} {}" width="{}" height="{}">"#,
+            min.x, min.y, size.x, size.y, size.x, size.y
+        );
+        if !self.name.is_empty() {
+            let _ = writeln!(svg, "<title>{}</title>", escape(&self.name));
+        }
+        let _ = writeln!(
+            svg,
+            r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#,
+            min.x,
+            min.y,
+            size.x,
+            size.y,
+            color(ThemeColor::Darker)
+        );
+
+        for layer in layers.iter() {
+            let _ = writeln!(
+                svg,
+                r#"<g id="{}" opacity="{}">"#,
+                escape(&layer.name),
+                layer.opacity
+            );
+            let (floor, walls) = self.merged_outlines(&layer.tiles);
+            for (outlines, fill) in [
+                (floor, color(ThemeColor::Lighter)),
+                (walls, color(ThemeColor::Dark)),
+            ] {
+                if !outlines.is_empty() {
+                    let _ = writeln!(
+                        svg,
+                        r#"<path fill="{fill}" fill-rule="evenodd" d="{}"/>"#,
+                        path_data(&outlines)
+                    );
+                }
+            }
+            let _ = writeln!(svg, "</g>");
+        }
+
+        let mut cells = Vec::from_iter(layers.iter().flat_map(|layer| layer.tiles.keys()));
+        cells.sort_by_key(|hex| (hex.y, hex.x));
+        cells.dedup();
+        if options.grid_lines {
+            let outlines = Vec::from_iter(cells.iter().map(|hex| self.cell_outline(**hex)));
+            let _ = writeln!(
+                svg,
+                r#"<path fill="none" stroke="{}" stroke-width="1" d="{}"/>"#,
+                color(ThemeColor::Normal),
+                path_data(&outlines)
+            );
+        }
+        if options.labels {
+            let _ = writeln!(
+                svg,
+                r#"<g fill="{}" font-family="sans-serif" font-size="{}" text-anchor="middle" dominant-baseline="middle">"#,
+                color(ThemeColor::Normal),
+                self.grid.size / 3.
+            );
+            for hex in cells {
+                let pos = self.grid.cell_to_world(*hex);
+                let _ = writeln!(
+                    svg,
+                    r#"<text x="{}" y="{}">{},{}</text>"#,
+                    pos.x, pos.y, hex.x, hex.y
+                );
+            }
+            let _ = writeln!(svg, "</g>");
+        }
+
+        let _ = writeln!(svg, "</svg>");
+        svg
+    }
+
+    fn cell_outline(&self, hex: Hex) -> Vec<Vec2> {
+        let pos = self.grid.cell_to_world(hex);
+        let outline = Vec::from_iter(
+            self.grid
+                .vertecies()
+                .iter()
+                .map(|v| *v * self.grid.size + pos),
+        );
+        // Square outlines include the side midpoints
+        if self.grid.is_hex() {
+            outline
+        } else {
+            Vec::from_iter(outline.into_iter().skip(1).step_by(2))
+        }
+    }
+
+    /// Floor and wall outlines of all tiles, with the sides shared by two tiles removed
+    fn merged_outlines(&self, tiles: &Chunks<Tile>) -> (Vec<Vec<Vec2>>, Vec<Vec<Vec2>>) {
+        let unit = match self.grid.kind {
+            GridKind::PointyHex => vec2(self.grid.inradius(), self.grid.size / 2.),
+            GridKind::FlatHex => vec2(self.grid.size / 2., self.grid.inradius()),
+            GridKind::Square => Vec2::splat(self.grid.size / 2.),
+        };
+        let mut floor = Outlines::new(self.grid.origin, unit);
+        let mut walls = Outlines::new(self.grid.origin, unit);
+        for (hex, tile) in tiles.iter() {
+            let Some((empty, full)) = self.tile_variants.get(tile) else {
+                continue;
+            };
+            let pos = self.grid.cell_to_world(*hex);
+            let polygons = std::iter::once((false, empty)).chain(full.iter().map(|p| (true, p)));
+            for (is_wall, polygon) in polygons {
+                let outlines = if is_wall { &mut walls } else { &mut floor };
+                if polygon.len() >= 3 {
+                    outlines.add(&Vec::from_iter(
+                        polygon.iter().map(|v| *v * self.grid.size + pos),
+                    ));
+                }
+            }
+        }
+        (floor.loops(), walls.loops())
+    }
+}
+
+type Key = (i64, i64);
+
+/// Union of polygons sharing the same winding, built from their boundary edges
+struct Outlines {
+    origin: Vec2,
+    /// Spacing of the lattice all cell corners and side midpoints lie on
+    unit: Vec2,
+    edges: HashMap<Key, Vec<Key>>,
+    points: HashMap<Key, Vec2>,
+}
+
+impl Outlines {
+    fn new(origin: Vec2, unit: Vec2) -> Self {
+        Self {
+            origin,
+            unit,
+            edges: HashMap::new(),
+            points: HashMap::new(),
+        }
+    }
+
+    fn key(&mut self, p: Vec2) -> Key {
+        let lattice = ((p - self.origin) / self.unit).round();
+        let key = (lattice.x as i64, lattice.y as i64);
+        self.points
+            .entry(key)
+            .or_insert(self.origin + lattice * self.unit);
+        key
+    }
+
+    fn add(&mut self, polygon: &[Vec2]) {
+        let keys = Vec::from_iter(polygon.iter().map(|p| self.key(*p)));
+        for (i, from) in keys.iter().enumerate() {
+            let to = keys[(i + 1) % keys.len()];
+            // A side shared with a neighbour is walked the other way round by it
+            if let Some(back) = self.edges.get_mut(&to)
+                && let Some(j) = back.iter().position(|k| k == from)
+            {
+                back.swap_remove(j);
+                continue;
+            }
+            self.edges.entry(*from).or_default().push(to);
+        }
+    }
+
+    fn loops(mut self) -> Vec<Vec<Vec2>> {
+        let mut starts = Vec::from_iter(self.edges.keys().copied());
+        starts.sort();
+        let mut loops = Vec::new();
+        for start in starts {
+            while let Some(mut next) = self.edges.get_mut(&start).and_then(|to| to.pop()) {
+                let mut keys = vec![start];
+                while next != start {
+                    keys.push(next);
+                    match self.edges.get_mut(&next).and_then(|to| to.pop()) {
+                        Some(to) => next = to,
+                        None => break,
+                    }
+                }
+                loops.push(simplify(&Vec::from_iter(
+                    keys.iter().map(|key| self.points[key]),
+                )));
+            }
+        }
+        loops
+    }
+}
+
+/// Drops points lying on the straight line between their neighbours
+fn simplify(points: &[Vec2]) -> Vec<Vec2> {
+    let n = points.len();
+    Vec::from_iter(
+        (0..n)
+            .filter(|i| {
+                let a = points[*i] - points[(i + n - 1) % n];
+                let b = points[(i + 1) % n] - points[*i];
+                a.perp_dot(b).abs() > 1e-3 * a.length() * b.length()
+            })
+            .map(|i| points[i]),
+    )
+}
+
+fn path_data(outlines: &[Vec<Vec2>]) -> String {
+    let mut d = String::new();
+    for outline in outlines {
+        for (i, p) in outline.iter().enumerate() {
+            let _ = write!(
+                d,
+                "{}{:.2} {:.2} ",
+                if i == 0 { "M" } else { "L" },
+                p.x,
+                p.y
+            );
+        }
+        d.push_str("Z ");
+    }
+    d.trim_end().to_string()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn walled(kind: GridKind, cells: &[Hex]) -> Map {
+        let mut map = Map::default();
+        map.set_grid(kind, 1.);
+        for hex in cells {
+            map.layers[0].tiles.insert(*hex, Tile::Full);
+        }
+        map
+    }
+
+    fn sorted(points: &[Vec2]) -> Vec<(i32, i32)> {
+        let mut points = Vec::from_iter(
+            points
+                .iter()
+                .map(|p| ((p.x * 100.).round() as i32, (p.y * 100.).round() as i32)),
+        );
+        points.sort();
+        points
+    }
+
+    #[test]
+    fn adjacent_walls_merge_into_one_outline() {
+        let map = walled(GridKind::Square, &[Hex::ZERO, Hex::new(1, 0)]);
+        let (floor, walls) = map.merged_outlines(&map.layers[0].tiles);
+        assert!(floor.is_empty());
+        assert_eq!(walls.len(), 1);
+        // The shared side is gone, and with it the points along the long sides
+        assert_eq!(
+            sorted(&walls[0]),
+            [(-50, -50), (-50, 50), (150, -50), (150, 50)]
+        );
+
+        let map = walled(GridKind::PointyHex, &[Hex::ZERO, Hex::new(1, 0)]);
+        let (_, walls) = map.merged_outlines(&map.layers[0].tiles);
+        assert_eq!(walls.len(), 1);
+        assert_eq!(walls[0].len(), 10);
+    }
+
+    #[test]
+    fn separate_walls_keep_their_own_outlines() {
+        let map = walled(GridKind::Square, &[Hex::ZERO, Hex::new(2, 0)]);
+        let (_, walls) = map.merged_outlines(&map.layers[0].tiles);
+        assert_eq!(walls.len(), 2);
+    }
+
+    #[test]
+    fn names_are_escaped() {
+        let mut map = walled(GridKind::Square, &[Hex::ZERO]);
+        map.name = r#"Fish & "Chips""#.into();
+        map.layers[0].name = "<floor>".into();
+        let svg = map.to_svg(&Theme::default(), &SvgOptions::default());
+        assert!(
+            svg.contains("<title>Fish &amp; &quot;Chips&quot;</title>"),
+            "{svg}"
+        );
+        assert!(svg.contains(r#"<g id="&lt;floor&gt;""#), "{svg}");
+    }
+}
@@ -0,0 +1,42 @@
+use serde::{Deserialize, Serialize};
+
+/// Material of a hex, independent of the wall shape painted on it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum Terrain {
+    #[default]
+    Normal,
+    Difficult,
+    Water,
+    Lava,
+    /// Seen through but not walked through
+    Glass,
+    /// Walked through but not seen through, like thick smoke
+    Fog,
+}
+
+impl Terrain {
+    pub const ALL: [Terrain; 6] = [
+        Terrain::Normal,
+        Terrain::Difficult,
+        Terrain::Water,
+        Terrain::Lava,
+        Terrain::Glass,
+        Terrain::Fog,
+    ];
+
+    /// Cost of entering a hex of this terrain, in hexes
+    pub fn movement_cost(&self) -> u32 {
+        match self {
+            Terrain::Normal | Terrain::Glass | Terrain::Fog => 1,
+            Terrain::Difficult | Terrain::Water | Terrain::Lava => 2,
+        }
+    }
+
+    pub fn blocks_sight(&self) -> bool {
+        matches!(self, Terrain::Fog)
+    }
+
+    pub fn blocks_movement(&self) -> bool {
+        matches!(self, Terrain::Glass)
+    }
+}
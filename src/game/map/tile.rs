@@ -1,11 +1,14 @@
-use std::{
-    collections::HashMap,
-    f32::{self, consts::FRAC_PI_6},
-};
+use std::collections::HashMap;
 
 use macroquad::prelude::*;
 use serde::{Deserialize, Serialize};
 
+use crate::game::map::mesh::MeshBuilder;
+
+/// Floor and wall parts of a cell.
+///
+/// The named variants are presets; `Mask` holds any other valid split, one bit per
+/// cell vertex: set in `full` for a wall vertex, in `both` for one on the floor's edge.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Tile {
     Empty,
@@ -13,6 +16,7 @@ pub enum Tile {
     Half { rotation: u8 },
     Large { rotation: u8 },
     Full,
+    Mask { full: u8, both: u8 },
 }
 
 impl Tile {
@@ -22,15 +26,38 @@ impl Tile {
         size: f32,
         empty_color: Color,
         full_color: Color,
-        tile_variants: &HashMap<Tile, (Vec<Vec2>, Vec<Vec2>)>,
+        tile_variants: &HashMap<Tile, (Vec<Vec2>, Vec<Vec<Vec2>>)>,
     ) {
-        if let Some((empty_v, full_v)) = tile_variants.get(self) {
+        if let Some((empty_v, walls)) = tile_variants.get(self) {
             Self::draw_vertecies(pos, size, empty_color, empty_v);
-            Self::draw_vertecies(pos, size, full_color, full_v);
+            for full_v in walls {
+                Self::draw_vertecies(pos, size, full_color, full_v);
+            }
         }
         draw_circle(pos.x, pos.y, 3., full_color);
     }
 
+    pub fn build_mesh(
+        &self,
+        builder: &mut MeshBuilder,
+        pos: Vec2,
+        size: f32,
+        empty_color: Color,
+        full_color: Color,
+        tile_variants: &HashMap<Tile, (Vec<Vec2>, Vec<Vec<Vec2>>)>,
+    ) {
+        if let Some((empty_v, walls)) = tile_variants.get(self) {
+            let polygons = [(empty_color, empty_v)]
+                .into_iter()
+                .chain(walls.iter().map(|full_v| (full_color, full_v)));
+            for (color, vertecies) in polygons {
+                let vertecies = Vec::from_iter(vertecies.iter().map(|v| *v * size + pos));
+                builder.fan(&vertecies, color);
+            }
+        }
+        builder.circle(pos, 3., 8, full_color);
+    }
+
     pub fn draw_vertecies(pos: Vec2, size: f32, color: Color, vertecies: &[Vec2]) {
         if vertecies.len() >= 3 {
             for i in 1..vertecies.len() - 1 {
@@ -44,35 +71,91 @@ impl Tile {
         }
     }
 
-    pub fn rotate(&mut self, delta: i8) {
-        match self {
-            Tile::Small { rotation } | Tile::Half { rotation } | Tile::Large { rotation } => {
-                *rotation = (*rotation as i8 + delta).rem_euclid(6) as u8;
-            }
-            _ => (),
+    /// Every tile variant on a grid with `rotations` distinct rotations
+    pub fn all(rotations: u8) -> Vec<Tile> {
+        let mut tiles = vec![Tile::Empty, Tile::Full];
+        for rotation in 0..rotations {
+            tiles.push(Tile::Small { rotation });
+            tiles.push(Tile::Half { rotation });
+            tiles.push(Tile::Large { rotation });
+        }
+        tiles
+    }
+
+    /// The mask of a split, see `is_valid_split`
+    pub fn from_mask(split: &[VertexType]) -> Tile {
+        let bits = |vertex_type: VertexType| {
+            split
+                .iter()
+                .enumerate()
+                .filter(|(_, v)| **v == vertex_type)
+                .fold(0, |bits, (i, _)| bits | 1 << i)
+        };
+        Tile::Mask {
+            full: bits(VertexType::Full),
+            both: bits(VertexType::Both),
         }
     }
+
+    /// Whether a split can be drawn as one floor polygon and a wall polygon per run of
+    /// wall vertices: walls meet the floor only at `Both` vertices, every `Both` vertex
+    /// touches a wall, and the floor has an area
+    pub fn is_valid_split(split: &[VertexType]) -> bool {
+        let n = split.len();
+        let floor = split.iter().filter(|v| **v != VertexType::Full).count();
+        if floor == 0 || split.iter().all(|v| *v == VertexType::Empty) {
+            return true;
+        }
+        floor >= 3
+            && (0..n).all(|i| {
+                let around = [split[(i + n - 1) % n], split[(i + 1) % n]];
+                match split[i] {
+                    VertexType::Empty => !around.contains(&VertexType::Full),
+                    VertexType::Both => around.contains(&VertexType::Full),
+                    VertexType::Full => true,
+                }
+            })
+    }
+
     pub fn is_empty_or_full(&self) -> bool {
         matches!(self, Tile::Empty | Tile::Full)
     }
 
-    pub fn split_to_tile_variant(split: [VertexType; 6]) -> (Vec<Vec2>, Vec<Vec2>) {
-        let mut empty = Vec::with_capacity(6);
-        let mut full = Vec::with_capacity(6);
-        for (i, vertex_type) in split.into_iter().enumerate() {
-            let v = Vec2::from_angle(i as f32 * f32::consts::FRAC_PI_3 + FRAC_PI_6);
-            match vertex_type {
-                VertexType::Empty => empty.push(v),
-                VertexType::Both => {
-                    empty.push(v);
-                    full.push(v);
-                }
+    /// The floor polygon, and one wall polygon per run of wall vertices
+    pub fn split_to_tile_variant(
+        split: &[VertexType],
+        vertecies: &[Vec2],
+    ) -> (Vec<Vec2>, Vec<Vec<Vec2>>) {
+        let n = split.len();
+        let empty = Vec::from_iter(
+            split
+                .iter()
+                .zip(vertecies)
+                .filter(|(vertex_type, _)| **vertex_type != VertexType::Full)
+                .map(|(_, v)| *v),
+        );
+        let Some(start) = split.iter().position(|v| *v != VertexType::Full) else {
+            return (empty, vec![vertecies.to_vec()]);
+        };
+        // Walk once around from a floor vertex, each run of walls closed by the vertices next to it
+        let mut walls = Vec::new();
+        let mut wall: Vec<Vec2> = Vec::new();
+        for i in (start..start + n + 1).map(|i| i % n) {
+            match split[i] {
                 VertexType::Full => {
-                    full.push(v);
+                    if wall.is_empty() {
+                        wall.push(vertecies[(i + n - 1) % n]);
+                    }
+                    wall.push(vertecies[i]);
+                }
+                _ if !wall.is_empty() => {
+                    wall.push(vertecies[i]);
+                    walls.push(std::mem::take(&mut wall));
                 }
+                _ => (),
             }
         }
-        (empty, full)
+        (empty, walls)
     }
 }
 
@@ -0,0 +1,586 @@
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{Context, Result, bail};
+use base64::Engine;
+use hexx::{Hex, HexOrientation, OffsetHexMode};
+use serde::Deserialize;
+
+use crate::game::map::{
+    Map,
+    grid::{Grid, GridKind},
+    layer::Layer,
+    terrain::Terrain,
+    tile::Tile,
+};
+
+/// Flip and rotation flags in the high bits of a Tiled global tile id
+const GID_FLAGS: u32 = 0xf000_0000;
+const FLIPPED_HORIZONTALLY: u32 = 0x8000_0000;
+const FLIPPED_VERTICALLY: u32 = 0x4000_0000;
+/// Flipped along the diagonal on orthogonal maps, turned by 60° on hexagonal ones
+const FLIPPED_DIAGONALLY: u32 = 0x2000_0000;
+/// Turned by 120° on hexagonal maps
+const ROTATED_120: u32 = 0x1000_0000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TiledOrientation {
+    Orthogonal,
+    /// Staggered along x (flat hexes) or y (pointy hexes), shoving odd or even rows
+    Hexagonal {
+        stagger_x: bool,
+        odd: bool,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct TiledLayer {
+    pub name: String,
+    /// Column, row and global tile id, flip flags included, of every non empty cell
+    pub cells: Vec<(i32, i32, u32)>,
+}
+
+/// Tile layers of a Tiled map, independent of the file format they came from
+#[derive(Debug, Clone)]
+pub struct TiledMap {
+    pub orientation: TiledOrientation,
+    pub layers: Vec<TiledLayer>,
+}
+
+/// User supplied translation of Tiled tile ids, stored as RON
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TiledMapping {
+    /// Tiled layer name to map layer name, layers not listed keep their name
+    #[serde(default)]
+    pub layers: HashMap<String, String>,
+    /// Global tile id (as shown in Tiled, plus the tileset's first gid) to what it becomes
+    pub tiles: HashMap<u32, TileMapping>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TileMapping {
+    #[serde(default)]
+    pub tile: Option<Tile>,
+    #[serde(default)]
+    pub terrain: Option<Terrain>,
+    /// Overrides the layer the tile is placed on
+    #[serde(default)]
+    pub layer: Option<String>,
+}
+
+impl TiledMapping {
+    pub fn from_ron(data: &str) -> Result<Self> {
+        Ok(ron::from_str(data)?)
+    }
+
+    /// `maps/cave.tmx` is mapped by `maps/cave.mapping.ron`
+    pub fn path_for(map: &str) -> String {
+        Path::new(map)
+            .with_extension("mapping.ron")
+            .to_string_lossy()
+            .to_string()
+    }
+}
+
+pub fn is_tiled_file(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| matches!(extension, "tmx" | "tmj"))
+}
+
+impl TiledMap {
+    /// Parses `.tmx` (XML) or `.tmj` (JSON) depending on the content
+    pub fn parse(data: &str) -> Result<Self> {
+        if data.trim_start().starts_with('<') {
+            Self::from_tmx(data)
+        } else {
+            Self::from_tmj(data)
+        }
+    }
+
+    pub fn from_tmx(data: &str) -> Result<Self> {
+        let document = roxmltree::Document::parse(data)?;
+        let map = document.root_element();
+        let orientation = parse_orientation(
+            map.attribute("orientation").unwrap_or_default(),
+            map.attribute("staggeraxis"),
+            map.attribute("staggerindex"),
+        )?;
+
+        let mut layers = Vec::new();
+        for layer in map.descendants().filter(|node| node.has_tag_name("layer")) {
+            let name = layer.attribute("name").unwrap_or_default().to_string();
+            let width = attribute(layer, "width")?;
+            let Some(data) = layer.children().find(|node| node.has_tag_name("data")) else {
+                continue;
+            };
+            let encoding = data.attribute("encoding");
+            if let Some(compression) = data.attribute("compression") {
+                bail!("layer {name}: {compression} compressed data is not supported");
+            }
+
+            let mut cells = Vec::new();
+            let chunks = Vec::from_iter(data.children().filter(|node| node.has_tag_name("chunk")));
+            if chunks.is_empty() {
+                let gids = parse_gids(encoding, data.text().unwrap_or_default(), data)?;
+                push_cells(&mut cells, &gids, 0, 0, width);
+            } else {
+                for chunk in chunks {
+                    let gids = parse_gids(encoding, chunk.text().unwrap_or_default(), chunk)?;
+                    push_cells(
+                        &mut cells,
+                        &gids,
+                        attribute(chunk, "x")?,
+                        attribute(chunk, "y")?,
+                        attribute(chunk, "width")?,
+                    );
+                }
+            }
+            layers.push(TiledLayer { name, cells });
+        }
+        Ok(Self {
+            orientation,
+            layers,
+        })
+    }
+
+    pub fn from_tmj(data: &str) -> Result<Self> {
+        let map: TmjMap = serde_json::from_str(data)?;
+        let orientation = parse_orientation(
+            &map.orientation,
+            map.staggeraxis.as_deref(),
+            map.staggerindex.as_deref(),
+        )?;
+        let mut layers = Vec::new();
+        flatten_tmj_layers(&map.layers, &mut layers)?;
+        Ok(Self {
+            orientation,
+            layers,
+        })
+    }
+
+    pub fn to_hex(&self, col: i32, row: i32) -> Hex {
+        match self.orientation {
+            TiledOrientation::Orthogonal => Hex::new(col, row),
+            TiledOrientation::Hexagonal { stagger_x, odd } => Hex::from_offset_coordinates(
+                [col, row],
+                if odd {
+                    OffsetHexMode::Odd
+                } else {
+                    OffsetHexMode::Even
+                },
+                if stagger_x {
+                    HexOrientation::Flat
+                } else {
+                    HexOrientation::Pointy
+                },
+            ),
+        }
+    }
+
+    /// `tile` flipped and turned as the flags of its global tile id say.
+    ///
+    /// Tiled flips a tile before turning it clockwise; a diagonal flip on an orthogonal map is a
+    /// quarter turn with the flips swapped.
+    pub fn orient_tile(&self, grid: &Grid, tile: Tile, flags: u32) -> Tile {
+        let has = |flag: u32| flags & flag != 0;
+        let (mut flip_x, mut flip_y) = (has(FLIPPED_HORIZONTALLY), has(FLIPPED_VERTICALLY));
+        let degrees = match self.orientation {
+            TiledOrientation::Orthogonal if has(FLIPPED_DIAGONALLY) => {
+                (flip_x, flip_y) = (flip_y, !flip_x);
+                90
+            }
+            TiledOrientation::Orthogonal => 0,
+            TiledOrientation::Hexagonal { .. } => {
+                60 * has(FLIPPED_DIAGONALLY) as i32 + 120 * has(ROTATED_120) as i32
+            }
+        };
+        let sides = grid.sides() as i32;
+        let mut tile = tile;
+        if flip_x {
+            tile = grid.mirror_tile(tile);
+        }
+        if flip_y {
+            tile = grid.rotate_tile(grid.mirror_tile(tile), sides / 2);
+        }
+        grid.rotate_tile(tile, degrees * sides / 360)
+    }
+
+    pub fn grid_kind(&self) -> GridKind {
+        match self.orientation {
+            TiledOrientation::Orthogonal => GridKind::Square,
+            TiledOrientation::Hexagonal {
+                stagger_x: true, ..
+            } => GridKind::FlatHex,
+            TiledOrientation::Hexagonal {
+                stagger_x: false, ..
+            } => GridKind::PointyHex,
+        }
+    }
+}
+
+impl Map {
+    /// Replaces the map with an imported Tiled map.
+    ///
+    /// Returns the tile ids missing from `mapping`, which are left out.
+    pub fn import_tiled(&mut self, tiled: &TiledMap, mapping: &TiledMapping) -> Vec<u32> {
+        self.set_grid(tiled.grid_kind(), self.grid.size);
+        self.layers = Self::default_layers();
+        self.active_layer = 0;
+        self.terrain.clear();
+        self.elevation.clear();
+        self.edges.clear();
+        self.lights.clear();
+        self.background = None;
+        self.invalidate_meshes();
+
+        let mut unmapped = Vec::new();
+        for tiled_layer in tiled.layers.iter() {
+            let layer_name = mapping
+                .layers
+                .get(&tiled_layer.name)
+                .unwrap_or(&tiled_layer.name);
+            for &(col, row, gid) in tiled_layer.cells.iter() {
+                let (gid, flags) = (gid & !GID_FLAGS, gid & GID_FLAGS);
+                let Some(tile_mapping) = mapping.tiles.get(&gid) else {
+                    unmapped.push(gid);
+                    continue;
+                };
+                let hex = tiled.to_hex(col, row);
+                if let Some(terrain) = tile_mapping.terrain {
+                    self.set_terrain(hex, terrain);
+                }
+                if let Some(tile) = tile_mapping.tile {
+                    let name = tile_mapping.layer.as_ref().unwrap_or(layer_name);
+                    let layer = match self.layers.iter().position(|layer| &layer.name == name) {
+                        Some(layer) => layer,
+                        None => {
+                            self.layers.push(Layer::new(name));
+                            self.layers.len() - 1
+                        }
+                    };
+                    let tile = tiled.orient_tile(&self.grid, tile, flags);
+                    self.layers[layer].tiles.insert(hex, tile);
+                }
+            }
+        }
+        unmapped.sort();
+        unmapped.dedup();
+        unmapped
+    }
+}
+
+fn parse_orientation(
+    orientation: &str,
+    stagger_axis: Option<&str>,
+    stagger_index: Option<&str>,
+) -> Result<TiledOrientation> {
+    match orientation {
+        "orthogonal" => Ok(TiledOrientation::Orthogonal),
+        "hexagonal" => Ok(TiledOrientation::Hexagonal {
+            stagger_x: stagger_axis == Some("x"),
+            odd: stagger_index != Some("even"),
+        }),
+        _ => bail!("unsupported Tiled map orientation {orientation:?}"),
+    }
+}
+
+fn attribute(node: roxmltree::Node, name: &str) -> Result<i32> {
+    node.attribute(name)
+        .with_context(|| format!("<{}> without {name}", node.tag_name().name()))?
+        .parse()
+        .with_context(|| format!("<{}> with invalid {name}", node.tag_name().name()))
+}
+
+/// Global tile ids of a `<data>` or `<chunk>`, flip flags included
+fn parse_gids(encoding: Option<&str>, text: &str, node: roxmltree::Node) -> Result<Vec<u32>> {
+    let gids = match encoding {
+        Some("csv") => text
+            .split(',')
+            .map(|gid| gid.trim())
+            .filter(|gid| !gid.is_empty())
+            .map(|gid| gid.parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()?,
+        Some("base64") => decode_base64_gids(text)?,
+        None => node
+            .children()
+            .filter(|child| child.has_tag_name("tile"))
+            .map(|tile| tile.attribute("gid").unwrap_or("0").parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()?,
+        Some(encoding) => bail!("unsupported layer encoding {encoding:?}"),
+    };
+    Ok(gids)
+}
+
+fn decode_base64_gids(text: &str) -> Result<Vec<u32>> {
+    let text: String = text.split_whitespace().collect();
+    let bytes = base64::engine::general_purpose::STANDARD.decode(text)?;
+    Ok(Vec::from_iter(bytes.chunks_exact(4).map(|gid| {
+        u32::from_le_bytes([gid[0], gid[1], gid[2], gid[3]])
+    })))
+}
+
+fn push_cells(cells: &mut Vec<(i32, i32, u32)>, gids: &[u32], x: i32, y: i32, width: i32) {
+    if width <= 0 {
+        return;
+    }
+    for (i, gid) in gids.iter().enumerate() {
+        if *gid & !GID_FLAGS != 0 {
+            let i = i as i32;
+            cells.push((x + i % width, y + i / width, *gid));
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct TmjMap {
+    orientation: String,
+    staggeraxis: Option<String>,
+    staggerindex: Option<String>,
+    #[serde(default)]
+    layers: Vec<TmjLayer>,
+}
+
+#[derive(Deserialize)]
+struct TmjLayer {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    width: i32,
+    data: Option<TmjData>,
+    encoding: Option<String>,
+    compression: Option<String>,
+    chunks: Option<Vec<TmjChunk>>,
+    /// Children of a group layer
+    layers: Option<Vec<TmjLayer>>,
+}
+
+#[derive(Deserialize)]
+struct TmjChunk {
+    x: i32,
+    y: i32,
+    width: i32,
+    data: TmjData,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TmjData {
+    Gids(Vec<u32>),
+    Base64(String),
+}
+
+impl TmjData {
+    fn gids(&self) -> Result<Vec<u32>> {
+        match self {
+            TmjData::Gids(gids) => Ok(gids.clone()),
+            TmjData::Base64(text) => decode_base64_gids(text),
+        }
+    }
+}
+
+fn flatten_tmj_layers(tmj_layers: &[TmjLayer], layers: &mut Vec<TiledLayer>) -> Result<()> {
+    for layer in tmj_layers {
+        match layer.kind.as_str() {
+            "group" => flatten_tmj_layers(layer.layers.as_deref().unwrap_or_default(), layers)?,
+            "tilelayer" => {
+                if let Some(compression) = layer.compression.as_deref().filter(|c| !c.is_empty()) {
+                    bail!(
+                        "layer {}: {compression} compressed data is not supported",
+                        layer.name
+                    );
+                }
+                if let Some(encoding) = layer.encoding.as_deref()
+                    && !matches!(encoding, "csv" | "base64")
+                {
+                    bail!("unsupported layer encoding {encoding:?}");
+                }
+                let mut cells = Vec::new();
+                if let Some(data) = &layer.data {
+                    push_cells(&mut cells, &data.gids()?, 0, 0, layer.width);
+                }
+                for chunk in layer.chunks.iter().flatten() {
+                    push_cells(
+                        &mut cells,
+                        &chunk.data.gids()?,
+                        chunk.x,
+                        chunk.y,
+                        chunk.width,
+                    );
+                }
+                layers.push(TiledLayer {
+                    name: layer.name.clone(),
+                    cells,
+                });
+            }
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tmx(orientation: &str, data: &str) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<map orientation="{orientation}" width="2" height="2">
+ <layer name="walls" width="2" height="2">
+  {data}
+ </layer>
+</map>"#
+        )
+    }
+
+    fn only_layer(map: &TiledMap) -> &[(i32, i32, u32)] {
+        assert_eq!(map.layers.len(), 1);
+        &map.layers[0].cells
+    }
+
+    const CELLS: [(i32, i32, u32); 3] = [(0, 0, 1), (0, 1, 2), (1, 1, 3)];
+
+    #[test]
+    fn tmx_data_decodes_alike_in_every_encoding() {
+        for data in [
+            r#"<data encoding="csv">1,0,
+2,3</data>"#,
+            r#"<data encoding="base64">
+   AQAAAAAAAAACAAAAAwAAAA==
+  </data>"#,
+            r#"<data><tile gid="1"/><tile/><tile gid="2"/><tile gid="3"/></data>"#,
+        ] {
+            let map = TiledMap::parse(&tmx("orthogonal", data)).unwrap();
+            assert_eq!(map.orientation, TiledOrientation::Orthogonal);
+            assert_eq!(only_layer(&map), CELLS, "{data}");
+        }
+    }
+
+    #[test]
+    fn tmx_chunks_are_placed_at_their_position() {
+        let data = r#"<data encoding="csv"><chunk x="-2" y="4" width="2" height="2">1,0,2,3</chunk></data>"#;
+        let map = TiledMap::parse(&tmx("orthogonal", data)).unwrap();
+        assert_eq!(only_layer(&map), [(-2, 4, 1), (-2, 5, 2), (-1, 5, 3)]);
+    }
+
+    #[test]
+    fn tmj_data_decodes_alike_as_array_and_base64() {
+        for data in [r#"[1, 0, 2, 3]"#, r#""AQAAAAAAAAACAAAAAwAAAA==""#] {
+            let json = format!(
+                r#"{{"orientation": "orthogonal", "layers": [{{"type": "group", "layers": [
+                    {{"type": "tilelayer", "name": "walls", "width": 2, "data": {data}}}
+                ]}}]}}"#
+            );
+            let map = TiledMap::parse(&json).unwrap();
+            assert_eq!(only_layer(&map), CELLS, "{data}");
+        }
+    }
+
+    #[test]
+    fn compressed_data_is_refused() {
+        let data = r#"<data encoding="base64" compression="zlib">eJw=</data>"#;
+        assert!(TiledMap::parse(&tmx("orthogonal", data)).is_err());
+    }
+
+    #[test]
+    fn flip_flags_stay_on_the_gid() {
+        let data = r#"<data encoding="base64">AQAAAAAAAAACAAAAAwAAgA==</data>"#;
+        let map = TiledMap::parse(&tmx("orthogonal", data)).unwrap();
+        assert_eq!(only_layer(&map)[2], (1, 1, 3 | FLIPPED_HORIZONTALLY));
+    }
+
+    fn hexagonal(stagger_x: bool, odd: bool) -> TiledMap {
+        TiledMap {
+            orientation: TiledOrientation::Hexagonal { stagger_x, odd },
+            layers: Vec::new(),
+        }
+    }
+
+    fn touch(map: &TiledMap, a: (i32, i32), b: (i32, i32)) -> bool {
+        map.to_hex(a.0, a.1)
+            .unsigned_distance_to(map.to_hex(b.0, b.1))
+            == 1
+    }
+
+    #[test]
+    fn staggered_rows_and_columns_shove_by_index() {
+        // Odd rows are shoved right, so the first cell of row 1 sits between two of row 0
+        let odd_rows = hexagonal(false, true);
+        assert!(touch(&odd_rows, (0, 1), (0, 0)) && touch(&odd_rows, (0, 1), (1, 0)));
+        let even_rows = hexagonal(false, false);
+        assert!(touch(&even_rows, (0, 1), (0, 0)) && touch(&even_rows, (0, 1), (-1, 0)));
+        // Odd columns are shoved down
+        let odd_columns = hexagonal(true, true);
+        assert!(touch(&odd_columns, (1, 0), (0, 0)) && touch(&odd_columns, (1, 0), (0, 1)));
+        let even_columns = hexagonal(true, false);
+        assert!(touch(&even_columns, (1, 0), (0, 0)) && touch(&even_columns, (1, 0), (0, -1)));
+        for map in [odd_rows, even_rows, odd_columns, even_columns] {
+            assert!(touch(&map, (0, 0), (1, 0)) || touch(&map, (0, 0), (0, 1)));
+            assert!(!touch(&map, (0, 0), (2, 0)) && !touch(&map, (0, 0), (0, 2)));
+        }
+    }
+
+    #[test]
+    fn flags_turn_and_flip_the_mapped_tile() {
+        let tile = Tile::Half { rotation: 0 };
+        let square = Grid::new(GridKind::Square, 1.);
+        let orthogonal = TiledMap {
+            orientation: TiledOrientation::Orthogonal,
+            layers: Vec::new(),
+        };
+        let orient = |flags| orthogonal.orient_tile(&square, tile, flags);
+        assert_eq!(orient(0), tile);
+        assert_eq!(
+            orient(FLIPPED_HORIZONTALLY | FLIPPED_DIAGONALLY),
+            square.rotate_tile(tile, 1)
+        );
+        assert_eq!(
+            orient(FLIPPED_HORIZONTALLY | FLIPPED_VERTICALLY),
+            square.rotate_tile(tile, 2)
+        );
+        assert_eq!(
+            orient(FLIPPED_VERTICALLY | FLIPPED_DIAGONALLY),
+            square.rotate_tile(tile, 3)
+        );
+        assert_eq!(orient(FLIPPED_HORIZONTALLY), square.mirror_tile(tile));
+
+        let hex = Grid::new(GridKind::PointyHex, 1.);
+        let tile = Tile::Large { rotation: 1 };
+        let map = hexagonal(false, true);
+        let orient = |flags| map.orient_tile(&hex, tile, flags);
+        assert_eq!(orient(FLIPPED_DIAGONALLY), hex.rotate_tile(tile, 1));
+        assert_eq!(orient(ROTATED_120), hex.rotate_tile(tile, 2));
+        assert_eq!(
+            orient(FLIPPED_DIAGONALLY | ROTATED_120),
+            hex.rotate_tile(tile, 3)
+        );
+        assert_eq!(orient(FLIPPED_HORIZONTALLY), hex.mirror_tile(tile));
+        assert_eq!(
+            orient(FLIPPED_HORIZONTALLY | FLIPPED_VERTICALLY),
+            hex.rotate_tile(tile, 3)
+        );
+    }
+
+    #[test]
+    fn import_places_flipped_tiles_turned() {
+        let data = r#"<data encoding="csv">1,0,0,2684354561</data>"#;
+        let tiled = TiledMap::parse(&tmx("orthogonal", data)).unwrap();
+        let mapping =
+            TiledMapping::from_ron("(tiles: { 1: (tile: Some(Half(rotation: 0))) })").unwrap();
+        let mut map = Map::default();
+        let unmapped = map.import_tiled(&tiled, &mapping);
+        assert!(unmapped.is_empty());
+        let tiles = &map.layers.iter().find(|l| l.name == "walls").unwrap().tiles;
+        assert_eq!(
+            tiles.get(&Hex::new(0, 0)),
+            Some(&Tile::Half { rotation: 0 })
+        );
+        assert_eq!(
+            tiles.get(&Hex::new(1, 1)),
+            Some(&map.grid.rotate_tile(Tile::Half { rotation: 0 }, 1))
+        );
+    }
+}
@@ -0,0 +1,270 @@
+use std::{collections::HashMap, f32::consts::TAU};
+
+use anyhow::{Result, bail};
+use hexx::Hex;
+use macroquad::prelude::*;
+
+use crate::game::map::{Map, chunks::Chunks, edge::Edge, grid::Grid, tile::Tile};
+
+/// A move of every cell of a map, keeping the cells next to each other
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MapTransform {
+    Translate(Hex),
+    /// Turned by `steps` sides around `pivot`, clockwise on screen
+    Rotate {
+        pivot: Hex,
+        steps: i32,
+    },
+    /// Across the axis through `pivot`, turned `axis` half sides from vertical
+    Mirror {
+        pivot: Hex,
+        axis: u8,
+    },
+}
+
+impl MapTransform {
+    pub fn cell(&self, grid: &Grid, cell: Hex) -> Hex {
+        match *self {
+            MapTransform::Translate(offset) => cell + offset,
+            MapTransform::Rotate { pivot, steps } => grid.rotate_cell(cell, pivot, steps),
+            MapTransform::Mirror { pivot, axis } => {
+                grid.rotate_cell(grid.mirror_cell(cell, pivot), pivot, axis as i32)
+            }
+        }
+    }
+
+    pub fn tile(&self, grid: &Grid, tile: Tile) -> Tile {
+        match *self {
+            MapTransform::Translate(_) => tile,
+            MapTransform::Rotate { steps, .. } => grid.rotate_tile(tile, steps),
+            MapTransform::Mirror { axis, .. } => {
+                grid.rotate_tile(grid.mirror_tile(tile), axis as i32)
+            }
+        }
+    }
+
+    pub fn edge(&self, grid: &Grid, edge: Edge) -> Edge {
+        let (a, b) = edge.hexes(grid);
+        let (a, b) = (self.cell(grid, a), self.cell(grid, b));
+        let direction = grid
+            .neighbors(a)
+            .iter()
+            .position(|n| *n == b)
+            .unwrap_or_default();
+        Edge::new(grid, a, direction as u8)
+    }
+}
+
+impl Map {
+    /// Moves every tile, terrain, height, edge, light and generation of the map.
+    ///
+    /// The background follows translations and rotations; as it cannot be mirrored, maps with
+    /// one are left as they are and mirroring fails. Generations are only kept when moved, as
+    /// a turned or mirrored run would not give the same cells.
+    pub fn transform(&mut self, transform: MapTransform) -> Result<()> {
+        if let MapTransform::Mirror { .. } = transform
+            && self.background.is_some()
+        {
+            bail!("cannot mirror a map with a background image");
+        }
+        let grid = self.grid;
+        let cell = |hex: Hex| transform.cell(&grid, hex);
+        for layer in self.layers.iter_mut() {
+            layer.tiles = Chunks::from_iter(
+                layer
+                    .tiles
+                    .iter()
+                    .map(|(hex, tile)| (cell(*hex), transform.tile(&grid, *tile))),
+            );
+        }
+        self.terrain = Chunks::from_iter(
+            self.terrain
+                .iter()
+                .map(|(hex, terrain)| (cell(*hex), *terrain)),
+        );
+        self.elevation = Chunks::from_iter(
+            self.elevation
+                .iter()
+                .map(|(hex, elevation)| (cell(*hex), *elevation)),
+        );
+        self.edges = HashMap::from_iter(
+            self.edges
+                .iter()
+                .map(|(edge, feature)| (transform.edge(&grid, *edge), *feature)),
+        );
+        for light in self.lights.iter_mut() {
+            light.hex = cell(light.hex);
+        }
+        if let MapTransform::Translate(_) = transform {
+            for generation in self.generations.iter_mut() {
+                generation.center = cell(generation.center);
+            }
+        } else if !self.generations.is_empty() {
+            warn!(
+                "Forgetting {} generator runs, they no longer reproduce the turned map",
+                self.generations.len()
+            );
+            self.generations.clear();
+        }
+        if let Some(background) = &mut self.background {
+            match transform {
+                MapTransform::Translate(offset) => {
+                    background.offset += grid.cell_to_world(offset) - grid.cell_to_world(Hex::ZERO);
+                }
+                MapTransform::Rotate { pivot, steps } => {
+                    let angle = steps as f32 * TAU / grid.sides() as f32;
+                    background.rotate_around(grid.cell_to_world(pivot), angle);
+                }
+                MapTransform::Mirror { .. } => (),
+            }
+        }
+        self.invalidate_meshes();
+        Ok(())
+    }
+
+    /// Every cell holding a tile, terrain, height, edge or light
+    pub fn used_cells(&self) -> Vec<Hex> {
+        let mut cells = Vec::from_iter(
+            self.layers
+                .iter()
+                .flat_map(|layer| layer.tiles.keys().copied())
+                .chain(self.terrain.keys().copied())
+                .chain(self.elevation.keys().copied())
+                .chain(self.edges.keys().map(|edge| edge.hex))
+                .chain(self.lights.iter().map(|light| light.hex)),
+        );
+        cells.sort_by_key(|cell| (cell.y, cell.x));
+        cells.dedup();
+        cells
+    }
+
+    /// The move putting the top left corner of the map's bounding box on cell (0, 0)
+    pub fn normalizing(&self) -> MapTransform {
+        let cells = self.used_cells();
+        if cells.is_empty() {
+            return MapTransform::Translate(Hex::ZERO);
+        }
+        let min = cells
+            .iter()
+            .map(|cell| self.grid.cell_to_world(*cell))
+            .fold(Vec2::INFINITY, |a, b| a.min(b));
+        MapTransform::Translate(-self.grid.world_to_cell(min))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::map::{
+        background::Background,
+        edge::{EdgeFeature, EdgeKind},
+        generator::{Generation, GeneratorKind},
+        grid::GridKind,
+        light::LightSource,
+    };
+
+    /// Every tile of the grid in a row, with a door and a light off to the side
+    fn sample(kind: GridKind) -> Map {
+        let mut map = Map::default();
+        map.set_grid(kind, 1.);
+        for (i, tile) in map.grid.all_tiles().into_iter().enumerate() {
+            let hex = Hex::new(i as i32 % 12 - 3, i as i32 / 12 + 1);
+            map.layers[0].tiles.insert(hex, tile);
+        }
+        let door = Edge::new(&map.grid, Hex::new(-2, -3), 1);
+        map.edges.insert(door, EdgeFeature::new(EdgeKind::Door));
+        map.lights.push(LightSource {
+            hex: Hex::new(4, -1),
+            range: 3.,
+            intensity: 1.,
+            color: 0xffaa00,
+        });
+        map
+    }
+
+    type Contents = (Vec<(Hex, Tile)>, Vec<(Edge, EdgeFeature)>, Vec<Hex>);
+
+    fn contents(map: &Map) -> Contents {
+        let mut tiles = Vec::from_iter(map.layers[0].tiles.iter().map(|(h, t)| (*h, *t)));
+        tiles.sort_by_key(|(hex, _)| (hex.y, hex.x));
+        let mut edges = Vec::from_iter(map.edges.iter().map(|(e, f)| (*e, *f)));
+        edges.sort_by_key(|(edge, _)| (edge.hex.y, edge.hex.x, edge.direction));
+        let lights = Vec::from_iter(map.lights.iter().map(|light| light.hex));
+        (tiles, edges, lights)
+    }
+
+    #[test]
+    fn a_full_turn_gives_back_the_map() {
+        for kind in [GridKind::PointyHex, GridKind::FlatHex, GridKind::Square] {
+            let mut map = sample(kind);
+            let original = contents(&map);
+            let pivot = Hex::new(1, -1);
+            for step in 1..=map.grid.sides() {
+                map.transform(MapTransform::Rotate { pivot, steps: 1 })
+                    .unwrap();
+                assert_eq!(
+                    contents(&map) == original,
+                    step == map.grid.sides(),
+                    "{kind:?}"
+                );
+            }
+            map.transform(MapTransform::Rotate { pivot, steps: -1 })
+                .unwrap();
+            map.transform(MapTransform::Rotate { pivot, steps: 1 })
+                .unwrap();
+            assert_eq!(contents(&map), original, "{kind:?}");
+        }
+    }
+
+    #[test]
+    fn mirroring_twice_gives_back_the_map() {
+        for kind in [GridKind::PointyHex, GridKind::FlatHex, GridKind::Square] {
+            let mut map = sample(kind);
+            let original = contents(&map);
+            for axis in 0..map.grid.sides() as u8 {
+                let mirror = MapTransform::Mirror {
+                    pivot: Hex::new(2, 0),
+                    axis,
+                };
+                map.transform(mirror).unwrap();
+                assert_ne!(contents(&map), original, "{kind:?} {axis}");
+                map.transform(mirror).unwrap();
+                assert_eq!(contents(&map), original, "{kind:?} {axis}");
+            }
+        }
+    }
+
+    #[test]
+    fn maps_with_a_background_are_not_mirrored() {
+        let mut map = sample(GridKind::PointyHex);
+        map.background = Some(Background::new("map.png", Vec2::ZERO));
+        let original = contents(&map);
+        let mirror = MapTransform::Mirror {
+            pivot: Hex::ZERO,
+            axis: 0,
+        };
+        assert!(map.transform(mirror).is_err());
+        assert_eq!(contents(&map), original);
+    }
+
+    #[test]
+    fn generations_follow_moves_and_are_forgotten_by_turns() {
+        let mut map = Map::default();
+        map.build_tile_variants();
+        map.generations.push(Generation {
+            kind: GeneratorKind::Caves,
+            seed: 1,
+            center: Hex::ZERO,
+            radius: 8,
+        });
+        map.transform(MapTransform::Translate(Hex::new(2, 1)))
+            .unwrap();
+        assert_eq!(map.generations[0].center, Hex::new(2, 1));
+        map.transform(MapTransform::Rotate {
+            pivot: Hex::ZERO,
+            steps: 1,
+        })
+        .unwrap();
+        assert!(map.generations.is_empty());
+    }
+}
@@ -0,0 +1,496 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    path::Path,
+};
+
+use anyhow::Result;
+use base64::Engine;
+use hexx::Hex;
+use macroquad::prelude::*;
+use serde::Deserialize;
+
+use crate::game::map::{
+    Map,
+    background::Background,
+    edge::{Edge, EdgeFeature, EdgeKind, EdgeState},
+    grid::Grid,
+    light::LightSource,
+    tile::{Tile, VertexType},
+};
+
+/// Point in grid squares
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct UvttPoint {
+    pub x: f32,
+    pub y: f32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct UvttResolution {
+    pub map_origin: UvttPoint,
+    pub map_size: UvttPoint,
+    pub pixels_per_grid: f32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct UvttPortal {
+    pub bounds: Vec<UvttPoint>,
+    #[serde(default)]
+    pub closed: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct UvttLight {
+    pub position: UvttPoint,
+    pub range: f32,
+    #[serde(default = "UvttLight::default_intensity")]
+    pub intensity: f32,
+    /// AARRGGBB
+    #[serde(default)]
+    pub color: String,
+}
+
+impl UvttLight {
+    fn default_intensity() -> f32 {
+        1.
+    }
+}
+
+/// Universal VTT map, as exported by Dungeondraft (.dd2vtt) and others
+#[derive(Debug, Clone, Deserialize)]
+pub struct UvttMap {
+    pub resolution: UvttResolution,
+    #[serde(default)]
+    pub line_of_sight: Vec<Vec<UvttPoint>>,
+    #[serde(default)]
+    pub objects_line_of_sight: Vec<Vec<UvttPoint>>,
+    #[serde(default)]
+    pub portals: Vec<UvttPortal>,
+    #[serde(default)]
+    pub lights: Vec<UvttLight>,
+    /// Base64 encoded PNG
+    #[serde(default)]
+    pub image: String,
+}
+
+impl UvttMap {
+    pub fn from_json(data: &str) -> Result<Self> {
+        Ok(serde_json::from_str(data)?)
+    }
+
+    pub fn image_bytes(&self) -> Result<Vec<u8>> {
+        let image: String = self.image.split_whitespace().collect();
+        Ok(base64::engine::general_purpose::STANDARD.decode(image)?)
+    }
+}
+
+pub fn is_uvtt_file(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| matches!(extension, "dd2vtt" | "df2vtt" | "uvtt"))
+}
+
+impl Map {
+    /// Replaces the map with an imported one, its background image read from `image`.
+    /// See `UvttMap::image_bytes` for writing that image out.
+    ///
+    /// One grid square of the import becomes one cell of `grid`.
+    pub fn import_uvtt(&mut self, uvtt: &UvttMap, image: &str) -> Result<()> {
+        let origin = vec2(uvtt.resolution.map_origin.x, uvtt.resolution.map_origin.y);
+        let cell_width = self.grid.inradius() * 2.;
+        let to_world = |p: &UvttPoint| (vec2(p.x, p.y) - origin) * cell_width;
+        let segments = |lines: &[Vec<UvttPoint>]| {
+            Vec::from_iter(lines.iter().flat_map(|line| {
+                line.windows(2)
+                    .map(|pair| (to_world(&pair[0]), to_world(&pair[1])))
+                    .collect::<Vec<_>>()
+            }))
+        };
+
+        let walls = [
+            segments(&uvtt.line_of_sight),
+            segments(&uvtt.objects_line_of_sight),
+        ]
+        .concat();
+        let portals = Vec::from_iter(uvtt.portals.iter().filter_map(|portal| {
+            let (first, last) = (portal.bounds.first()?, portal.bounds.last()?);
+            Some((to_world(first), to_world(last), portal.closed))
+        }));
+        let doors = Vec::from_iter(portals.iter().map(|(from, to, _)| (*from, *to)));
+        let size = vec2(uvtt.resolution.map_size.x, uvtt.resolution.map_size.y) * cell_width;
+
+        self.layers = Self::default_layers();
+        self.active_layer = 0;
+        self.terrain.clear();
+        self.elevation.clear();
+        self.edges.clear();
+        self.lights.clear();
+        self.generations.clear();
+        self.invalidate_meshes();
+
+        for (hex, tile) in rasterize_walls(&self.grid, Vec2::ZERO, size, &walls, &doors) {
+            self.layers[0].tiles.insert(hex, tile);
+        }
+
+        for (from, to, closed) in portals {
+            let state = if closed {
+                EdgeState::Closed
+            } else {
+                EdgeState::Open
+            };
+            for edge in edges_along(&self.grid, from, to) {
+                self.edges.insert(
+                    edge,
+                    EdgeFeature {
+                        kind: EdgeKind::Door,
+                        state,
+                    },
+                );
+            }
+        }
+
+        for light in uvtt.lights.iter() {
+            let color = light.color.trim().trim_start_matches('#');
+            self.lights.push(LightSource {
+                hex: self.grid.world_to_cell(to_world(&light.position)),
+                range: light.range,
+                intensity: light.intensity,
+                // The alpha byte is dropped
+                color: u32::from_str_radix(color, 16).map_or(0xffffff, |argb| argb & 0xffffff),
+            });
+        }
+
+        self.background = None;
+        if !uvtt.image.is_empty() {
+            self.background = Some(Background {
+                scale: cell_width / uvtt.resolution.pixels_per_grid,
+                ..Background::new(image, Vec2::ZERO)
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Cells covering `[min, max]` with the tile that best follows the walls.
+///
+/// The area reachable from outside the map without crossing a wall or a door
+/// is void, everything else is floor. Maps without any enclosed area are all
+/// floor, with every cell a wall passes through filled.
+fn rasterize_walls(
+    grid: &Grid,
+    min: Vec2,
+    max: Vec2,
+    walls: &[(Vec2, Vec2)],
+    doors: &[(Vec2, Vec2)],
+) -> Vec<(Hex, Tile)> {
+    let pad = grid.circumradius() * 2.;
+    let in_rect = |p: Vec2, pad: f32| p.cmpge(min - pad).all() && p.cmple(max + pad).all();
+    let radius = ((max - min).length() / 2. / grid.inradius()).ceil() as u32 + 2;
+    let cells = Vec::from_iter(
+        grid.range(grid.world_to_cell((min + max) / 2.), radius)
+            .into_iter()
+            .filter(|cell| in_rect(grid.cell_to_world(*cell), pad)),
+    );
+    let included = HashSet::<Hex>::from_iter(cells.iter().copied());
+
+    let barriers = [walls, doors].concat();
+    let mut buckets: HashMap<Hex, HashSet<usize>> = HashMap::new();
+    for (i, (a, b)) in barriers.iter().enumerate() {
+        let steps = (a.distance(*b) / (grid.size / 4.)).ceil().max(1.) as usize;
+        for step in 0..=steps {
+            let cell = grid.world_to_cell(a.lerp(*b, step as f32 / steps as f32));
+            for near in [vec![cell], grid.neighbors(cell), grid.diagonals(cell)].concat() {
+                buckets.entry(near).or_default().insert(i);
+            }
+        }
+    }
+    let nearby = |cells: &[Hex]| {
+        HashSet::<usize>::from_iter(
+            cells
+                .iter()
+                .filter_map(|cell| buckets.get(cell))
+                .flatten()
+                .copied(),
+        )
+    };
+    let blocked = |a: Vec2, b: Vec2, near: &HashSet<usize>| {
+        near.iter()
+            .any(|i| segments_intersect(a, b, barriers[*i].0, barriers[*i].1))
+    };
+
+    // Cell centers and outline points, shared between neighbouring cells
+    let mut points: Vec<Vec2> = Vec::new();
+    let mut point_ids: HashMap<(i64, i64), usize> = HashMap::new();
+    let mut point_id = |p: Vec2| {
+        let key = ((p.x * 16.).round() as i64, (p.y * 16.).round() as i64);
+        *point_ids.entry(key).or_insert_with(|| {
+            points.push(p);
+            points.len() - 1
+        })
+    };
+    let outline = grid.vertecies();
+    let mut centers: HashMap<Hex, usize> = HashMap::new();
+    let mut outlines: HashMap<Hex, Vec<usize>> = HashMap::new();
+    for cell in cells.iter() {
+        let pos = grid.cell_to_world(*cell);
+        centers.insert(*cell, point_id(pos));
+        outlines.insert(
+            *cell,
+            Vec::from_iter(outline.iter().map(|v| point_id(*v * grid.size + pos))),
+        );
+    }
+
+    let mut links: Vec<Vec<usize>> = vec![Vec::new(); points.len()];
+    let mut crossed = HashSet::new();
+    for cell in cells.iter() {
+        let center = centers[cell];
+        let near = nearby(&[*cell]);
+        for point in outlines[cell].iter() {
+            if blocked(points[center], points[*point], &near) {
+                crossed.insert(*cell);
+            } else {
+                links[center].push(*point);
+                links[*point].push(center);
+            }
+        }
+        for neighbor in grid.neighbors(*cell) {
+            if !included.contains(&neighbor) {
+                continue;
+            }
+            let other = centers[&neighbor];
+            if !blocked(points[center], points[other], &nearby(&[*cell, neighbor])) {
+                links[center].push(other);
+            }
+        }
+    }
+
+    let mut outside = vec![false; points.len()];
+    let mut queue = VecDeque::from_iter(
+        cells
+            .iter()
+            .filter(|cell| !in_rect(grid.cell_to_world(**cell), 0.))
+            .map(|cell| centers[cell]),
+    );
+    while let Some(point) = queue.pop_front() {
+        if std::mem::replace(&mut outside[point], true) {
+            continue;
+        }
+        queue.extend(links[point].iter().filter(|p| !outside[**p]));
+    }
+
+    let on_wall = Vec::from_iter(points.iter().map(|p| {
+        let near = nearby(&[grid.world_to_cell(*p)]);
+        near.iter().any(|i| {
+            *i < walls.len() && distance_to_segment(*p, walls[*i].0, walls[*i].1) < grid.size / 16.
+        })
+    }));
+    let enclosed = (0..points.len()).any(|p| !outside[p] && !on_wall[p]);
+    // None when the point could be either, lying on a wall
+    let is_floor = |p: usize| {
+        if on_wall[p] {
+            None
+        } else if enclosed {
+            Some(!outside[p])
+        } else {
+            Some(in_rect(points[p], 0.))
+        }
+    };
+
+    let floors = Vec::from_iter(cells.iter().map(|cell| {
+        let flags = Vec::from_iter(outlines[cell].iter().map(|p| is_floor(*p)));
+        let has_floor = is_floor(centers[cell]) == Some(true) || flags.contains(&Some(true));
+        (*cell, flags, has_floor)
+    }));
+    let has_floor = HashSet::<Hex>::from_iter(
+        floors
+            .iter()
+            .filter(|(_, _, has_floor)| *has_floor)
+            .map(|(cell, _, _)| *cell),
+    );
+
+    let candidates = grid.all_tiles();
+    let mut tiles = Vec::new();
+    for (cell, flags, floor) in floors {
+        let tile = if !floor {
+            if !grid.neighbors(cell).iter().any(|n| has_floor.contains(n)) {
+                continue;
+            }
+            Tile::Full
+        } else if !enclosed && crossed.contains(&cell) {
+            Tile::Full
+        } else {
+            *candidates
+                .iter()
+                .min_by_key(|tile| {
+                    grid.tile_split(**tile)
+                        .iter()
+                        .zip(flags.iter())
+                        .filter(|(split, flag)| match (split, flag) {
+                            (VertexType::Both, _) | (_, None) => false,
+                            (VertexType::Full, Some(floor)) => *floor,
+                            (VertexType::Empty, Some(floor)) => !*floor,
+                        })
+                        .count()
+                })
+                .unwrap_or(&Tile::Empty)
+        };
+        tiles.push((cell, tile));
+    }
+    tiles
+}
+
+/// Edges lying along the segment `from` - `to`, their middle between its ends
+fn edges_along(grid: &Grid, from: Vec2, to: Vec2) -> HashSet<Edge> {
+    let steps = (from.distance(to) / (grid.size / 4.)).ceil().max(1.) as usize;
+    let mut edges = HashSet::new();
+    for step in 0..=steps {
+        let edge = Edge::from_world_pos(grid, from.lerp(to, step as f32 / steps as f32));
+        let (a, b) = edge.endpoints(grid);
+        let middle = (a + b) / 2.;
+        // Edges only touching an end of the segment belong to the wall beyond it
+        let along = (middle - from).dot(to - from) / from.distance_squared(to).max(1e-6);
+        if 0. < along && along < 1. && distance_to_segment(middle, from, to) < grid.size * 0.6 {
+            edges.insert(edge);
+        }
+    }
+    edges
+}
+
+fn segments_intersect(p1: Vec2, p2: Vec2, q1: Vec2, q2: Vec2) -> bool {
+    let d1 = (q2 - q1).perp_dot(p1 - q1);
+    let d2 = (q2 - q1).perp_dot(p2 - q1);
+    let d3 = (p2 - p1).perp_dot(q1 - p1);
+    let d4 = (p2 - p1).perp_dot(q2 - p1);
+    if d1 == 0. && d2 == 0. {
+        // Collinear, overlapping when their bounding boxes do
+        return p1.min(p2).cmple(q1.max(q2)).all() && q1.min(q2).cmple(p1.max(p2)).all();
+    }
+    d1 * d2 <= 0. && d3 * d4 <= 0.
+}
+
+fn distance_to_segment(p: Vec2, a: Vec2, b: Vec2) -> f32 {
+    let ab = b - a;
+    let t = if ab.length_squared() > 0. {
+        ((p - a).dot(ab) / ab.length_squared()).clamp(0., 1.)
+    } else {
+        0.
+    };
+    p.distance(a + ab * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::map::grid::GridKind;
+
+    /// A room of three by three squares, walled all around or with a door in the middle of
+    /// its top wall
+    fn room(door: bool) -> String {
+        let (walls, portals): (&[[f32; 2]], _) = if door {
+            (
+                &[
+                    [1.5, 0.5],
+                    [0.5, 0.5],
+                    [0.5, 3.5],
+                    [3.5, 3.5],
+                    [3.5, 0.5],
+                    [2.5, 0.5],
+                ],
+                r#"[{"bounds": [{"x": 1.5, "y": 0.5}, {"x": 2.5, "y": 0.5}], "closed": true}]"#,
+            )
+        } else {
+            (
+                &[[0.5, 0.5], [0.5, 3.5], [3.5, 3.5], [3.5, 0.5], [0.5, 0.5]],
+                "[]",
+            )
+        };
+        let line = Vec::from_iter(
+            walls
+                .iter()
+                .map(|[x, y]| format!(r#"{{"x": {x}, "y": {y}}}"#)),
+        );
+        format!(
+            r##"{{
+                "resolution": {{
+                    "map_origin": {{"x": 0, "y": 0}},
+                    "map_size": {{"x": 4, "y": 4}},
+                    "pixels_per_grid": 64
+                }},
+                "line_of_sight": [[{}]],
+                "portals": {portals},
+                "lights": [
+                    {{"position": {{"x": 2, "y": 2}}, "range": 3, "color": "80ff8000"}},
+                    {{"position": {{"x": 1, "y": 1}}, "range": 1, "color": "#ffé"}},
+                    {{"position": {{"x": 3, "y": 3}}, "range": 1}}
+                ]
+            }}"##,
+            line.join(", ")
+        )
+    }
+
+    fn import(door: bool) -> Map {
+        let mut map = Map::default();
+        map.set_grid(GridKind::Square, 1.);
+        let uvtt = UvttMap::from_json(&room(door)).unwrap();
+        map.import_uvtt(&uvtt, "room.png").unwrap();
+        map
+    }
+
+    #[test]
+    fn walls_are_rasterized_along_their_lines() {
+        let map = import(false);
+        let mut tiles = Vec::from_iter(map.layers[0].tiles.iter().map(|(h, t)| (*h, *t)));
+        tiles.sort_by_key(|(hex, _)| (hex.y, hex.x));
+        let mut expected = Vec::new();
+        for y in 0..=4 {
+            for x in 0..=4 {
+                let (inside, corner) = ((1..=3).contains(&x), (1..=3).contains(&y));
+                match (inside, corner) {
+                    (true, true) => expected.push((Hex::new(x, y), Tile::Empty)),
+                    (true, false) | (false, true) => expected.push((Hex::new(x, y), Tile::Full)),
+                    // Corners touch no floor
+                    (false, false) => (),
+                }
+            }
+        }
+        assert_eq!(tiles, expected);
+        assert!(map.edges.is_empty());
+    }
+
+    #[test]
+    fn portals_become_doors_on_the_edges_they_cover() {
+        let map = import(true);
+        let doors = Vec::from_iter(map.edges.iter().map(|(edge, feature)| {
+            let mut hexes = <[Hex; 2]>::from(edge.hexes(&map.grid));
+            hexes.sort_by_key(|hex| (hex.y, hex.x));
+            (hexes, *feature)
+        }));
+        let closed = EdgeFeature {
+            kind: EdgeKind::Door,
+            state: EdgeState::Closed,
+        };
+        assert_eq!(doors, [([Hex::new(2, 0), Hex::new(2, 1)], closed)]);
+
+        let mut hex_map = Map::default();
+        hex_map.build_tile_variants();
+        let uvtt = UvttMap::from_json(&room(true)).unwrap();
+        hex_map.import_uvtt(&uvtt, "room.png").unwrap();
+        assert!(!hex_map.edges.is_empty());
+    }
+
+    #[test]
+    fn light_colors_drop_the_alpha_byte() {
+        let map = import(false);
+        let lights = Vec::from_iter(map.lights.iter().map(|light| (light.hex, light.color)));
+        assert_eq!(
+            lights,
+            [
+                (Hex::new(2, 2), 0xff8000),
+                // Not hexadecimal, or missing
+                (Hex::new(1, 1), 0xffffff),
+                (Hex::new(3, 3), 0xffffff),
+            ]
+        );
+    }
+}
@@ -0,0 +1,244 @@
+use std::collections::HashSet;
+
+use hexx::Hex;
+
+use crate::game::map::{Map, grid::Grid, tile::Tile};
+
+/// Wall tiles picked by which of the touching cells are floor
+#[derive(Default)]
+pub struct WallTiles {
+    grid: Grid,
+    openings: Vec<(Tile, Vec<bool>)>,
+}
+
+impl WallTiles {
+    pub fn new(grid: &Grid) -> Self {
+        Self {
+            grid: *grid,
+            openings: Vec::from_iter(
+                grid.all_tiles()
+                    .into_iter()
+                    .map(|tile| (tile, grid.tile_openings(tile))),
+            ),
+        }
+    }
+
+    /// The wall for a cell with the floor around it, in `Grid::touching` order.
+    ///
+    /// Floor in one unbroken run gets the same walls as always, see `WallTiles::run_tile`.
+    /// Otherwise the tile whose floor best reaches the floor around it wins: opening towards a
+    /// cell that is not floor costs twice as much as walling off one that is, so walls never
+    /// leak; ties go to the tile listed first by `Grid::all_tiles`.
+    pub fn pick(&self, floor: &[bool]) -> Tile {
+        if let Some(tile) = self.run_tile(floor) {
+            return tile;
+        }
+        self.openings
+            .iter()
+            .min_by_key(|(_, openings)| mismatch(openings, floor))
+            .map(|(tile, _)| *tile)
+            .unwrap_or(Tile::Full)
+    }
+
+    /// The preset wall for floor touching in at most one unbroken run around the cell.
+    ///
+    /// Hexes open up to the run: Large, Half and Small for two to four cells, no wall for five
+    /// or six and a full one below two. Squares stay solid walls.
+    fn run_tile(&self, floor: &[bool]) -> Option<Tile> {
+        // Around the cell, with the corners between the sides on squares
+        let ring = if self.grid.is_hex() {
+            floor.to_vec()
+        } else {
+            let (sides, corners) = floor.split_at(self.grid.sides());
+            Vec::from_iter(sides.iter().zip(corners).flat_map(|(s, c)| [*s, *c]))
+        };
+        let n = ring.len();
+        let starts = Vec::from_iter((0..n).filter(|i| ring[*i] && !ring[(i + n - 1) % n]));
+        let count = ring.iter().filter(|floor| **floor).count();
+        if starts.len() > 1 {
+            return None;
+        }
+        if !self.grid.is_hex() {
+            return Some(Tile::Full);
+        }
+        let start = starts.first().copied().unwrap_or_default() as i32;
+        Some(match count {
+            5.. => Tile::Empty,
+            4 => self.grid.rotate_tile(Tile::Small { rotation: 3 }, start),
+            3 => self.grid.rotate_tile(Tile::Half { rotation: 2 }, start),
+            2 => self.grid.rotate_tile(Tile::Large { rotation: 5 }, start),
+            _ => Tile::Full,
+        })
+    }
+}
+
+/// How badly a tile's openings fit the floor around it, see `WallTiles::pick`
+pub fn mismatch(openings: &[bool], floor: &[bool]) -> u32 {
+    openings
+        .iter()
+        .zip(floor)
+        .map(|(open, floor)| match (open, floor) {
+            (true, false) => 2,
+            (false, true) => 1,
+            _ => 0,
+        })
+        .sum()
+}
+
+impl Map {
+    /// New tiles of the `cells` that are not floor on `layer`: a wall when floor touches them,
+    /// no tile otherwise. Cells already holding that tile are left out.
+    pub fn wall_changes(
+        &self,
+        layer: usize,
+        cells: impl IntoIterator<Item = Hex>,
+    ) -> Vec<(Hex, Option<Tile>)> {
+        let walls = &self.wall_tiles;
+        let tiles = &self.layers[layer].tiles;
+        let is_floor = |cell: &Hex| tiles.get(cell) == Some(&Tile::Empty);
+        let cells = HashSet::<Hex>::from_iter(cells);
+        let mut changes = Vec::from_iter(
+            cells
+                .into_iter()
+                .filter(|cell| !is_floor(cell))
+                .filter_map(|cell| {
+                    let floor = Vec::from_iter(self.grid.touching(cell).iter().map(is_floor));
+                    let tile = floor.contains(&true).then(|| walls.pick(&floor));
+                    (tiles.get(&cell) != tile.as_ref()).then_some((cell, tile))
+                }),
+        );
+        changes.sort_by_key(|(cell, _)| (cell.y, cell.x));
+        changes
+    }
+
+    /// Cells without a tile touching the floor of `layer`
+    pub fn floor_border(&self, layer: usize) -> Vec<Hex> {
+        let tiles = &self.layers[layer].tiles;
+        let border = HashSet::<Hex>::from_iter(
+            tiles
+                .iter()
+                .filter(|(_, tile)| **tile == Tile::Empty)
+                .flat_map(|(cell, _)| self.grid.touching(*cell))
+                .filter(|cell| !tiles.contains_key(cell)),
+        );
+        Vec::from_iter(border)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::map::grid::GridKind;
+
+    /// Floor around a hex in one run of `count` cells from neighbour `start`
+    fn run(count: usize, start: usize) -> Vec<bool> {
+        Vec::from_iter((0..6).map(|i| (i + 6 - start) % 6 < count))
+    }
+
+    fn floor_map(cells: impl IntoIterator<Item = Hex>) -> Map {
+        let mut map = Map::default();
+        map.build_tile_variants();
+        for cell in cells {
+            map.active_tiles_mut().insert(cell, Tile::Empty);
+        }
+        map
+    }
+
+    #[test]
+    fn hex_walls_open_exactly_to_one_run_of_floor() {
+        for kind in [GridKind::PointyHex, GridKind::FlatHex] {
+            let grid = Grid::new(kind, 1.);
+            let walls = WallTiles::new(&grid);
+            for start in 0..6 {
+                assert_eq!(walls.pick(&run(0, start)), Tile::Full);
+                assert_eq!(walls.pick(&run(1, start)), Tile::Full);
+                for count in 2..=4 {
+                    let floor = run(count, start);
+                    assert_eq!(grid.tile_openings(walls.pick(&floor)), floor, "{kind:?}");
+                }
+                assert_eq!(walls.pick(&run(5, start)), Tile::Empty);
+                assert_eq!(walls.pick(&run(6, start)), Tile::Empty);
+            }
+        }
+    }
+
+    #[test]
+    fn hex_walls_keep_the_preset_table() {
+        let grid = Grid::default();
+        let walls = WallTiles::new(&grid);
+        assert_eq!(walls.pick(&run(2, 0)), Tile::Large { rotation: 5 });
+        assert_eq!(walls.pick(&run(3, 0)), Tile::Half { rotation: 2 });
+        assert_eq!(walls.pick(&run(4, 0)), Tile::Small { rotation: 3 });
+        assert_eq!(walls.pick(&run(2, 1)), Tile::Large { rotation: 0 });
+        assert_eq!(walls.pick(&run(3, 4)), Tile::Half { rotation: 0 });
+    }
+
+    #[test]
+    fn broken_floor_never_opens_towards_the_void() {
+        let grid = Grid::default();
+        let walls = WallTiles::new(&grid);
+        for code in 0..1 << 6 {
+            let floor = Vec::from_iter((0..6).map(|i| code & 1 << i != 0));
+            if walls.run_tile(&floor).is_some() {
+                continue;
+            }
+            let openings = grid.tile_openings(walls.pick(&floor));
+            for (open, floor) in openings.iter().zip(&floor) {
+                assert!(!open || *floor, "{floor:?} opens {openings:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn square_walls_stay_full_for_one_run() {
+        let grid = Grid::new(GridKind::Square, 1.);
+        let walls = WallTiles::new(&grid);
+        // Two sides and the corner between them
+        let corner = [true, true, false, false, true, false, false, false];
+        assert_eq!(walls.pick(&corner), Tile::Full);
+        // Opposite sides are two runs
+        let opposite = [true, false, true, false, false, false, false, false];
+        let openings = grid.tile_openings(walls.pick(&opposite));
+        for (open, floor) in openings.iter().zip(opposite) {
+            assert!(!open || floor);
+        }
+    }
+
+    #[test]
+    fn wall_changes_wall_the_floor_border() {
+        let mut map = floor_map(Hex::ZERO.range(1));
+        let mut border = map.floor_border(Map::FLOOR_LAYER);
+        border.sort_by_key(|cell| (cell.y, cell.x));
+        let mut ring = Vec::from_iter(Hex::ZERO.ring(2));
+        ring.sort_by_key(|cell| (cell.y, cell.x));
+        assert_eq!(border, ring);
+
+        let changes = map.wall_changes(Map::FLOOR_LAYER, border.clone());
+        assert_eq!(changes.len(), 12);
+        for (cell, tile) in changes {
+            let tile = tile.unwrap();
+            let floor = Vec::from_iter(map.grid.touching(cell).iter().map(|c| c.length() <= 1));
+            // Corners of the ring touch a single floor cell
+            let expected = if floor.iter().filter(|floor| **floor).count() > 1 {
+                floor
+            } else {
+                vec![false; 6]
+            };
+            assert_eq!(map.grid.tile_openings(tile), expected, "{cell:?}");
+            map.active_tiles_mut().insert(cell, tile);
+        }
+        assert!(map.wall_changes(Map::FLOOR_LAYER, border).is_empty());
+        assert!(map.floor_border(Map::FLOOR_LAYER).is_empty());
+    }
+
+    #[test]
+    fn wall_changes_skip_floor_and_clear_walls_away_from_it() {
+        let mut map = floor_map([Hex::ZERO]);
+        let far = Hex::new(5, 0);
+        map.active_tiles_mut().insert(far, Tile::Full);
+        assert_eq!(
+            map.wall_changes(Map::FLOOR_LAYER, [Hex::ZERO, far]),
+            vec![(far, None)]
+        );
+    }
+}
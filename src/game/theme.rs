@@ -2,8 +2,11 @@
 
 use macroquad::color::Color;
 
+use crate::game::map::terrain::Terrain;
+
 pub struct Theme {
     colors: [u32; 5],
+    terrain_colors: [u32; 6],
 }
 
 impl Default for Theme {
@@ -16,6 +19,14 @@ impl Default for Theme {
                 0xd8c3a2, // #d8c3a2
                 0xf2e7d4, // #f2e7d4
             ],
+            terrain_colors: [
+                0xd8c3a2, // #d8c3a2 Normal
+                0x8a7b4f, // #8a7b4f Difficult
+                0x5b7f95, // #5b7f95 Water
+                0xb8542f, // #b8542f Lava
+                0xa9c6c9, // #a9c6c9 Glass
+                0x8f8a86, // #8f8a86 Fog
+            ],
         }
     }
 }
@@ -28,6 +39,10 @@ impl Theme {
     pub fn color(&self, color: ThemeColor) -> Color {
         Color::from_hex(self.colors[color as isize as usize])
     }
+
+    pub fn terrain_color(&self, terrain: Terrain) -> Color {
+        Color::from_hex(self.terrain_colors[terrain as isize as usize])
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -2,10 +2,22 @@ pub mod game;
 
 use anyhow::Result;
 
-use crate::game::Game;
+use crate::game::{Game, cli::Command};
 
-#[macroquad::main("myvtt")]
-async fn main() -> Result<()> {
+fn main() -> Result<()> {
+    let args = Vec::from_iter(std::env::args().skip(1));
+    if let Some(command) = Command::parse(&args)? {
+        return command.run();
+    }
+    macroquad::Window::new("myvtt", async {
+        if let Err(err) = run().await {
+            macroquad::logging::error!("Error: {:?}", err);
+        }
+    });
+    Ok(())
+}
+
+async fn run() -> Result<()> {
     let mut game = Game::default();
     game.load().await?;
     game.run().await?;